@@ -0,0 +1,706 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::decimal::{Decimal, DEFAULT_PRECISION};
+use num_bigint::BigInt;
+use num_traits::{One, Signed};
+
+use crate::number::{Number, Word};
+use crate::operations::ParseError;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    /// `n√x`, the n-th root of x.
+    Root,
+    And,
+    Or,
+    Xor,
+    ShiftLeft,
+    ShiftRight,
+    RotateLeft,
+    RotateRight,
+}
+
+/// Unary operators bind tighter than `x` but looser than `^`, so `-2^2` is `-(2^2)`.
+const UNARY_PRECEDENCE: u8 = 7;
+
+/// `%` binds tighter than `^`, so `10%^2` is `(10%)^2`.
+const PERCENT_PRECEDENCE: u8 = 9;
+
+impl BinaryOperator {
+    /// Bitwise operators bind looser than `+` and `-`, as in C: `1+1«2` is `2«2`.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::Xor => 2,
+            BinaryOperator::And => 3,
+            BinaryOperator::ShiftLeft
+            | BinaryOperator::ShiftRight
+            | BinaryOperator::RotateLeft
+            | BinaryOperator::RotateRight => 4,
+            BinaryOperator::Add | BinaryOperator::Subtract => 5,
+            BinaryOperator::Multiply | BinaryOperator::Divide => 6,
+            BinaryOperator::Power | BinaryOperator::Root => 8,
+        }
+    }
+
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self, BinaryOperator::Power | BinaryOperator::Root)
+    }
+
+    pub fn symbol(&self) -> char {
+        match self {
+            BinaryOperator::Add => '+',
+            BinaryOperator::Subtract => '-',
+            BinaryOperator::Multiply => 'x',
+            BinaryOperator::Divide => '/',
+            BinaryOperator::Power => '^',
+            BinaryOperator::Root => '√',
+            BinaryOperator::And => '&',
+            BinaryOperator::Or => '|',
+            BinaryOperator::Xor => '⊻',
+            BinaryOperator::ShiftLeft => '«',
+            BinaryOperator::ShiftRight => '»',
+            BinaryOperator::RotateLeft => '↺',
+            BinaryOperator::RotateRight => '↻',
+        }
+    }
+
+    fn apply<N: Number>(&self, lhs: N, rhs: N, context: &Context) -> Result<N, ParseError> {
+        let precision = context.working_precision();
+        let word = context.word;
+
+        match self {
+            BinaryOperator::Add => Ok(lhs + rhs),
+            BinaryOperator::Subtract => Ok(lhs - rhs),
+            BinaryOperator::Multiply => Ok(lhs * rhs),
+            BinaryOperator::Divide => lhs.checked_div(&rhs, precision),
+            BinaryOperator::Power => power(&lhs, &rhs, precision),
+            BinaryOperator::Root => nth_root(&lhs, &rhs, precision),
+            BinaryOperator::And => bitwise(&lhs, &rhs, word, |lhs, rhs| Ok(lhs & rhs)),
+            BinaryOperator::Or => bitwise(&lhs, &rhs, word, |lhs, rhs| Ok(lhs | rhs)),
+            BinaryOperator::Xor => bitwise(&lhs, &rhs, word, |lhs, rhs| Ok(lhs ^ rhs)),
+            BinaryOperator::ShiftLeft => bitwise(&lhs, &rhs, word, |lhs, rhs| {
+                let shifted = lhs << word.shift_amount(&rhs)?;
+
+                // Wrapped in programmer mode, where the exact result shows the overflow
+                if !context.wrapping && word.wrap(&shifted).1 {
+                    return Err(ParseError::OutOfRange);
+                }
+                Ok(shifted)
+            }),
+            BinaryOperator::ShiftRight => bitwise(&lhs, &rhs, word, |lhs, rhs| Ok(lhs >> word.shift_amount(&rhs)?)),
+            BinaryOperator::RotateLeft => bitwise(&lhs, &rhs, word, |lhs, rhs| Ok(word.rotate_left(&lhs, &rhs))),
+            BinaryOperator::RotateRight => bitwise(&lhs, &rhs, word, |lhs, rhs| Ok(word.rotate_left(&lhs, &-rhs))),
+        }
+    }
+}
+
+/// Bitwise operators only take integers, which they read as two's
+/// complement `word`s. Bits pushed out of the word are lost, though a left
+/// shift losing some is out of range unless results wrap around.
+fn bitwise<N, F>(lhs: &N, rhs: &N, word: Word, operation: F) -> Result<N, ParseError>
+where
+    N: Number,
+    F: FnOnce(BigInt, BigInt) -> Result<BigInt, ParseError>,
+{
+    let (Some(lhs), Some(rhs)) = (lhs.to_bigint(), rhs.to_bigint()) else {
+        return Err(ParseError::NotAnInteger);
+    };
+    let value = operation(word.wrap(&lhs).0, rhs)?;
+
+    N::from_decimal(&Decimal::from(word.wrap(&value).0))
+}
+
+/// Two's complement wraparound of an integer result to `word`.
+fn wrap<N: Number>(value: N, word: Word) -> Result<N, ParseError> {
+    match value.to_bigint() {
+        Some(integer) => N::from_decimal(&Decimal::from(word.wrap(&integer).0)),
+        None => Ok(value),
+    }
+}
+
+/// `value` divided by 100, which integer modes only take when it comes out
+/// whole rather than truncate a percentage to 0.
+fn hundredths<N: Number>(value: N, precision: u64) -> Result<N, ParseError> {
+    let hundred = N::from_decimal(&Decimal::from(100))?;
+    let quotient = value.checked_div(&hundred, precision)?;
+
+    if N::INTEGRAL && !(quotient.clone() * hundred - value).is_zero() {
+        return Err(ParseError::NotAnInteger);
+    }
+    Ok(quotient)
+}
+
+/// `base^exponent` wrapped around to `word`, by modular exponentiation
+/// rather than computing a huge power. Negative exponents are left to
+/// `power`, their result being 0 or ±1.
+fn word_power<N: Number>(base: &N, exponent: &N, word: Word, precision: u64) -> Result<N, ParseError> {
+    let (Some(integer_base), Some(integer_exponent)) = (base.to_bigint(), exponent.to_bigint()) else {
+        return power(base, exponent, precision);
+    };
+    if integer_exponent.is_negative() {
+        return power(base, exponent, precision);
+    }
+    let value = integer_base.modpow(&integer_exponent, &(BigInt::one() << word.bits));
+
+    N::from_decimal(&Decimal::from(word.wrap(&value).0))
+}
+
+/// Integer exponents stay exact, anything else goes through `f64`.
+fn power<N: Number>(base: &N, exponent: &N, precision: u64) -> Result<N, ParseError> {
+    let Some(exponent) = exponent.to_i64() else {
+        return N::from_f64(base.to_f64().powf(exponent.to_f64()));
+    };
+
+    if base.is_zero() {
+        return match exponent {
+            ..0 => Err(ParseError::DivisionByZero),
+            0 => N::from_decimal(&Decimal::from(1)),
+            _ => Ok(base.clone()),
+        };
+    }
+    // Refuse before computing, exact backends would build a huge number
+    if (base.to_f64().abs().log10() * exponent as f64).abs() > N::MAX_MAGNITUDE {
+        return Err(ParseError::OutOfRange);
+    }
+    base.powi(exponent, precision)
+}
+
+/// Odd roots of negative numbers are real, whereas `powf` would return NaN.
+fn nth_root<N: Number>(degree: &N, radicand: &N, precision: u64) -> Result<N, ParseError> {
+    let degree_as_integer = degree.to_i64();
+
+    if degree_as_integer == Some(2) {
+        return radicand.sqrt(precision);
+    }
+
+    let degree_is_odd = degree_as_integer.is_some_and(|degree| degree % 2 != 0);
+    let (degree, radicand) = (degree.to_f64(), radicand.to_f64());
+
+    if radicand < 0.0 && degree_is_odd {
+        N::from_f64(-(-radicand).powf(1.0 / degree))
+    } else {
+        N::from_f64(radicand.powf(1.0 / degree))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnaryOperator {
+    Plus,
+    Negate,
+    SquareRoot,
+    /// `~x`, every bit of the word flipped.
+    Not,
+}
+
+impl UnaryOperator {
+    pub fn symbol(&self) -> char {
+        match self {
+            UnaryOperator::Plus => '+',
+            UnaryOperator::Negate => '-',
+            UnaryOperator::SquareRoot => '√',
+            UnaryOperator::Not => '~',
+        }
+    }
+
+    fn apply<N: Number>(&self, operand: N, context: &Context) -> Result<N, ParseError> {
+        match self {
+            UnaryOperator::Plus => Ok(operand),
+            UnaryOperator::Negate => Ok(-operand),
+            UnaryOperator::SquareRoot => operand.sqrt(context.working_precision()),
+            UnaryOperator::Not => bitwise(&operand, &operand, context.word, |operand, _| Ok(!operand)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum AngleMode {
+    #[default]
+    Degrees,
+    Radians,
+    Gradians,
+}
+
+impl AngleMode {
+    pub const ALL: [AngleMode; 3] = [AngleMode::Degrees, AngleMode::Radians, AngleMode::Gradians];
+
+    /// Reads back the `Display` name, e.g. `DEG`.
+    pub fn from_name(name: &str) -> Option<AngleMode> {
+        AngleMode::ALL.into_iter().find(|mode| mode.to_string() == name)
+    }
+
+    pub fn next(&self) -> AngleMode {
+        match self {
+            AngleMode::Degrees => AngleMode::Radians,
+            AngleMode::Radians => AngleMode::Gradians,
+            AngleMode::Gradians => AngleMode::Degrees,
+        }
+    }
+
+    fn to_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleMode::Degrees => angle.to_radians(),
+            AngleMode::Radians => angle,
+            AngleMode::Gradians => angle * std::f64::consts::PI / 200.0,
+        }
+    }
+}
+
+impl fmt::Display for AngleMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AngleMode::Degrees => write!(f, "DEG"),
+            AngleMode::Radians => write!(f, "RAD"),
+            AngleMode::Gradians => write!(f, "GRAD"),
+        }
+    }
+}
+
+/// Digits carried past `Context::precision` in intermediate results, so
+/// that `(10/3)x3` rounds back to `10`.
+const GUARD_DIGITS: u64 = 8;
+
+/// Name of the previous result in expressions, as in `ansx2`.
+pub const ANSWER: &str = "ans";
+
+/// Settings an expression is evaluated under.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Context {
+    pub angle_mode: AngleMode,
+    /// Significant digits of the result.
+    pub precision: u64,
+    /// What `ans` stands for, zero until a first result.
+    pub answer: Expr,
+    /// Integers bitwise operators work on.
+    pub word: Word,
+    /// Whether every intermediate result wraps around to `word`, as in
+    /// programmer mode.
+    pub wrapping: bool,
+    /// What each variable stands for, as assigned by `rate=0.07`.
+    pub variables: BTreeMap<String, Expr>,
+    /// Functions defined by `f(x,y)=x^2+y`, by name.
+    pub functions: BTreeMap<String, UserFunction>,
+}
+
+impl Default for Context {
+    fn default() -> Context {
+        Context {
+            angle_mode: AngleMode::default(),
+            precision: DEFAULT_PRECISION,
+            answer: Expr::Number(Decimal::zero()),
+            word: Word::default(),
+            wrapping: false,
+            variables: BTreeMap::new(),
+            functions: BTreeMap::new(),
+        }
+    }
+}
+
+impl Context {
+    fn working_precision(&self) -> u64 {
+        self.precision + GUARD_DIGITS
+    }
+
+    /// Whether `name` is one of the variables or functions.
+    pub fn defines(&self, name: &str) -> bool {
+        self.variables.contains_key(name) || self.functions.contains_key(name)
+    }
+}
+
+/// Calls of user functions nested deeper than this fail, which stops a
+/// function calling itself for ever.
+pub const MAX_CALL_DEPTH: usize = 64;
+
+/// Whether `name` is taken by `ans`, a constant or a function, and so
+/// cannot be assigned.
+pub fn is_reserved_name(name: &str) -> bool {
+    name == ANSWER || Constant::from_name(name).is_some() || Function::from_name(name).is_some()
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Constant {
+    Pi,
+    E,
+}
+
+impl Constant {
+    pub fn from_name(name: &str) -> Option<Constant> {
+        match name {
+            "π" | "pi" => Some(Constant::Pi),
+            "e" => Some(Constant::E),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Constant::Pi => "π",
+            Constant::E => "e",
+        }
+    }
+
+    /// Known to 50 significant digits.
+    fn value(&self, precision: u64) -> Decimal {
+        let digits = match self {
+            Constant::Pi => "3.1415926535897932384626433832795028841971693993751",
+            Constant::E => "2.7182818284590452353602874713526624977572470936999",
+        };
+
+        digits.parse::<Decimal>().unwrap().round(precision)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Ln,
+    Log,
+    Exp,
+    Abs,
+    Sqrt,
+}
+
+impl Function {
+    pub const ALL: [Function; 8] = [
+        Function::Sin,
+        Function::Cos,
+        Function::Tan,
+        Function::Ln,
+        Function::Log,
+        Function::Exp,
+        Function::Abs,
+        Function::Sqrt,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Function> {
+        Function::ALL.into_iter().find(|function| function.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Function::Sin => "sin",
+            Function::Cos => "cos",
+            Function::Tan => "tan",
+            Function::Ln => "ln",
+            Function::Log => "log",
+            Function::Exp => "exp",
+            Function::Abs => "abs",
+            Function::Sqrt => "sqrt",
+        }
+    }
+
+    /// `abs` and `sqrt` are exact, the others are computed on an `f64`.
+    /// `tan` is undefined where the cosine of the angle is zero, as at 90°.
+    fn apply<N: Number>(&self, argument: N, context: &Context) -> Result<N, ParseError> {
+        let angle = || context.angle_mode.to_radians(argument.to_f64());
+
+        match self {
+            Function::Sin => N::from_f64(snap_to_zero(angle().sin())),
+            Function::Cos => N::from_f64(snap_to_zero(angle().cos())),
+            Function::Tan if snap_to_zero(angle().cos()) == 0.0 => Err(ParseError::UndefinedResult),
+            Function::Tan => N::from_f64(snap_to_zero(angle().tan())),
+            Function::Ln => N::from_f64(argument.to_f64().ln()),
+            Function::Log => N::from_f64(argument.to_f64().log10()),
+            Function::Exp => N::from_f64(argument.to_f64().exp()),
+            Function::Abs => Ok(argument.abs()),
+            Function::Sqrt => argument.sqrt(context.working_precision()),
+        }
+    }
+}
+
+/// A function defined by the user, called as `f(3,4)`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UserFunction {
+    pub parameters: Vec<String>,
+    pub body: Expr,
+}
+
+impl UserFunction {
+    /// The definition as typed back, e.g. `f(x,y)=x^2+y`.
+    pub fn definition(&self, name: &str) -> String {
+        format!("{name}({})={}", self.parameters.join(","), self.body)
+    }
+}
+
+/// Arguments of the user function being evaluated, bound to its
+/// parameters, and how many calls deep it is.
+struct Frame<'a, N> {
+    bindings: Vec<(&'a str, N)>,
+    depth: usize,
+}
+
+impl<N> Frame<'_, N> {
+    /// Outside of any user function.
+    fn root() -> Self {
+        Frame { bindings: vec![], depth: 0 }
+    }
+}
+
+/// `sin(180)` gives 1.2e-16 because π is not exact in binary; such noise is 0.
+fn snap_to_zero(value: f64) -> f64 {
+    if value.abs() < 1e-15 {
+        0.0
+    } else {
+        value
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    Number(Decimal),
+    Constant(Constant),
+    /// The previous result, `Context::answer`.
+    Answer,
+    /// A parameter of the user function being evaluated, or else a name
+    /// defined in `Context::variables`.
+    Variable(String),
+    /// `x%`, a hundredth of `x`. As the right operand of `+` or `-` it is a
+    /// percentage of the left one instead, so `200+10%` is `220`.
+    Percent(Box<Expr>),
+    Unary {
+        operator: UnaryOperator,
+        operand: Box<Expr>,
+    },
+    Binary {
+        operator: BinaryOperator,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Call {
+        function: Function,
+        argument: Box<Expr>,
+    },
+    /// A call of `Context::functions`.
+    UserCall {
+        name: String,
+        arguments: Vec<Expr>,
+    },
+}
+
+impl Expr {
+    pub fn binary(operator: BinaryOperator, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary {
+            operator,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    pub fn unary(operator: UnaryOperator, operand: Expr) -> Expr {
+        Expr::Unary {
+            operator,
+            operand: Box::new(operand),
+        }
+    }
+
+    pub fn percent(operand: Expr) -> Expr {
+        Expr::Percent(Box::new(operand))
+    }
+
+    /// Whether the expression is a percentage, possibly signed as in `-10%`.
+    fn is_percent(&self) -> bool {
+        match self {
+            Expr::Percent(_) => true,
+            Expr::Unary { operator: UnaryOperator::Plus | UnaryOperator::Negate, operand } => operand.is_percent(),
+            _ => false,
+        }
+    }
+
+    pub fn call(function: Function, argument: Expr) -> Expr {
+        Expr::Call {
+            function,
+            argument: Box::new(argument),
+        }
+    }
+
+    /// Evaluates with a few guard digits, then rounds to `context.precision`.
+    pub fn evaluate<N: Number>(&self, context: &Context) -> Result<N, ParseError> {
+        self.evaluate_unrounded::<N>(context, &Frame::root())?.finalize(context.precision)
+    }
+
+    fn evaluate_unrounded<'a, N: Number>(&self, context: &'a Context, frame: &Frame<'a, N>) -> Result<N, ParseError> {
+        let value = self.evaluate_node::<N>(context, frame)?;
+
+        if context.wrapping {
+            return wrap(value, context.word);
+        }
+        Ok(value)
+    }
+
+    fn evaluate_node<'a, N: Number>(&self, context: &'a Context, frame: &Frame<'a, N>) -> Result<N, ParseError> {
+        let precision = context.working_precision();
+
+        match self {
+            Expr::Number(value) => N::from_decimal(value),
+            Expr::Constant(constant) => N::from_decimal(&constant.value(precision)),
+            Expr::Answer => context.answer.evaluate_unrounded(context, &Frame::root()),
+            Expr::Variable(name) => {
+                if let Some((_, value)) = frame.bindings.iter().find(|(parameter, _)| parameter == name) {
+                    return Ok(value.clone());
+                }
+                match context.variables.get(name) {
+                    Some(value) => value.evaluate_unrounded(context, &Frame::root()),
+                    None => Err(ParseError::UndefinedVariable { name: name.clone() }),
+                }
+            },
+            Expr::Percent(operand) => hundredths(operand.evaluate_unrounded(context, frame)?, precision),
+            // Multiplied before dividing by 100, which integer modes could not do first
+            Expr::Binary { operator: operator @ (BinaryOperator::Add | BinaryOperator::Subtract), lhs, rhs } if rhs.is_percent() => {
+                let lhs = lhs.evaluate_unrounded::<N>(context, frame)?;
+                let rate = rhs.evaluate_hundredfold::<N>(context, frame)?;
+
+                operator.apply(lhs.clone(), hundredths(lhs * rate, precision)?, context)
+            },
+            Expr::Binary { operator: BinaryOperator::Multiply, lhs, rhs } if lhs.is_percent() != rhs.is_percent() => {
+                let (value, rate) = if rhs.is_percent() { (lhs, rhs) } else { (rhs, lhs) };
+                let value = value.evaluate_unrounded::<N>(context, frame)?;
+
+                hundredths(value * rate.evaluate_hundredfold(context, frame)?, precision)
+            },
+            // The exponent is not wrapped, `3^400` in `i8` not being `3^-112`
+            Expr::Binary { operator: BinaryOperator::Power, lhs, rhs } if context.wrapping => {
+                let base = lhs.evaluate_unrounded::<N>(context, frame)?;
+                let unwrapped = Context { wrapping: false, ..context.clone() };
+                let exponent = rhs.evaluate_unrounded::<N>(&unwrapped, frame)?;
+
+                word_power(&base, &exponent, context.word, precision)
+            },
+            Expr::Binary { operator: BinaryOperator::Divide, lhs, rhs } if rhs.is_percent() => {
+                let lhs = lhs.evaluate_unrounded::<N>(context, frame)?;
+                let rate = rhs.evaluate_hundredfold::<N>(context, frame)?;
+
+                (lhs * N::from_decimal(&Decimal::from(100))?).checked_div(&rate, precision)
+            },
+            Expr::Unary { operator, operand } => operator.apply(operand.evaluate_unrounded(context, frame)?, context),
+            Expr::Binary { operator, lhs, rhs } => {
+                operator.apply(lhs.evaluate_unrounded(context, frame)?, rhs.evaluate_unrounded(context, frame)?, context)
+            },
+            Expr::Call { function, argument } => function.apply(argument.evaluate_unrounded(context, frame)?, context),
+            Expr::UserCall { name, arguments } => Expr::call_user_function(name, arguments, context, frame),
+        }
+    }
+
+    /// A percentage, possibly signed, times 100, i.e. without its `%`.
+    fn evaluate_hundredfold<'a, N: Number>(&self, context: &'a Context, frame: &Frame<'a, N>) -> Result<N, ParseError> {
+        match self {
+            Expr::Percent(operand) => operand.evaluate_unrounded(context, frame),
+            Expr::Unary { operator, operand } => operator.apply(operand.evaluate_hundredfold(context, frame)?, context),
+            expr => Ok(expr.evaluate_unrounded::<N>(context, frame)? * N::from_decimal(&Decimal::from(100))?),
+        }
+    }
+
+    /// Evaluates the arguments in the caller's frame, then the body in a new
+    /// frame where they are bound to the parameters.
+    fn call_user_function<'a, N: Number>(name: &str, arguments: &[Expr], context: &'a Context, frame: &Frame<'a, N>) -> Result<N, ParseError> {
+        let Some(function) = context.functions.get(name) else {
+            return Err(ParseError::UndefinedFunction { name: name.to_string() });
+        };
+        if arguments.len() != function.parameters.len() {
+            return Err(ParseError::WrongArgumentCount {
+                name: name.to_string(),
+                expected: function.parameters.len(),
+                found: arguments.len(),
+            });
+        }
+        if frame.depth == MAX_CALL_DEPTH {
+            return Err(ParseError::TooManyNestedCalls);
+        }
+
+        let bindings = function.parameters
+            .iter()
+            .zip(arguments)
+            .map(|(parameter, argument)| Ok((parameter.as_str(), argument.evaluate_unrounded(context, frame)?)))
+            .collect::<Result<Vec<(&str, N)>, ParseError>>()?;
+
+        function.body.evaluate_unrounded(context, &Frame { bindings, depth: frame.depth + 1 })
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Number(_)
+            | Expr::Constant(_)
+            | Expr::Answer
+            | Expr::Variable(_)
+            | Expr::Call { .. }
+            | Expr::UserCall { .. } => u8::MAX,
+            Expr::Unary { .. } => UNARY_PRECEDENCE,
+            Expr::Percent(_) => PERCENT_PRECEDENCE,
+            Expr::Binary { operator, .. } => operator.precedence(),
+        }
+    }
+}
+
+/// A whole line of input: an expression, or the definition of a variable
+/// or a function.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement {
+    Expression(Expr),
+    /// `name=value`, which also gives `value` to `name`.
+    Assignment {
+        name: String,
+        value: Expr,
+    },
+    /// `name(parameters)=body`, evaluated at each call.
+    Definition {
+        name: String,
+        function: UserFunction,
+    },
+}
+
+/// Prints the expression back in the calculator notation, with only the
+/// parentheses needed to keep the same tree when parsed again.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Number(value) => write!(f, "{value}"),
+            Expr::Constant(constant) => write!(f, "{}", constant.name()),
+            Expr::Answer => write!(f, "{ANSWER}"),
+            Expr::Variable(name) => write!(f, "{name}"),
+            Expr::Percent(operand) => {
+                if operand.precedence() < PERCENT_PRECEDENCE {
+                    write!(f, "({operand})%")
+                } else {
+                    write!(f, "{operand}%")
+                }
+            },
+            Expr::Unary { operator, operand } => {
+                if operand.precedence() < UNARY_PRECEDENCE {
+                    write!(f, "{}({operand})", operator.symbol())
+                } else {
+                    write!(f, "{}{operand}", operator.symbol())
+                }
+            },
+            Expr::Binary { operator, lhs, rhs } => {
+                let right_associative = operator.is_right_associative();
+
+                if lhs.precedence() < operator.precedence()
+                    || (lhs.precedence() == operator.precedence() && right_associative) {
+                    write!(f, "({lhs})")?;
+                } else {
+                    write!(f, "{lhs}")?;
+                }
+                write!(f, "{}", operator.symbol())?;
+                if rhs.precedence() < operator.precedence()
+                    || (rhs.precedence() == operator.precedence() && !right_associative) {
+                    write!(f, "({rhs})")
+                } else {
+                    write!(f, "{rhs}")
+                }
+            },
+            Expr::Call { function, argument } => write!(f, "{}({argument})", function.name()),
+            Expr::UserCall { name, arguments } => {
+                let arguments: Vec<String> = arguments.iter().map(Expr::to_string).collect();
+
+                write!(f, "{name}({})", arguments.join(","))
+            },
+        }
+    }
+}
@@ -3,21 +3,60 @@ use fltk::enums::{Color, Key, Shortcut};
 use fltk::prelude::{ButtonExt, WidgetExt};
 use fltk::{button::Button, prelude::WidgetBase};
 
-use crate::calculator::{settings, MessageEmit};
+use crate::ast::{Constant, Function};
+use crate::calculator::settings;
+use crate::engine::MessageEmit;
 
 pub struct MyButton {
-    // button: Button
+    button: Button
 }
 
 impl MyButton {
-    pub fn new(c: char, position: (i32, i32)) -> MyButton {
+    /// `key` is the character typed for the button (`d` being delete, `n` the
+    /// sign toggle and `r` the square root), a hexadecimal digit `A` to `F`,
+    /// `AC` to clear, a radix prefix such as `0x`, a bitwise operator, the
+    /// name of a function or constant, a memory key such as `M+`, `drg` for
+    /// the angle mode, `frac` for the fraction form, `word` and `sign` for
+    /// the word of programmer mode, or `swap`, `roll`, `drop` and `enter`
+    /// for the stack of RPN mode.
+    pub fn new(key: &'static str, position: (i32, i32)) -> MyButton {
         let (s, _r) = app::channel::<MessageEmit>();
-        let mut value: String = c.to_string();
-        let mut key: Key = Key::from_char(c);
-       
-        if c == 'd' {
+        let c: char = key.chars().next().unwrap();
+        let mut value: String = key.to_string();
+        let mut shortcut: Key = if key.len() == 1 { Key::from_char(c) } else { Key::None };
+
+        if key == "d" {
             value = "@<-".to_string();
-            key = Key::BackSpace;
+            shortcut = Key::BackSpace;
+        } else if key == "n" {
+            value = "+/-".to_string();
+            shortcut = Key::F9;
+        } else if key == "r" {
+            value = "√".to_string();
+        } else if key == "drg" {
+            value = "DRG".to_string();
+        } else if key == "pi" {
+            value = "π".to_string();
+        } else if key == "frac" {
+            value = "a→b/c".to_string();
+        } else if key == "AC" {
+            shortcut = Key::Escape;
+        } else if key == "&" {
+            // A single `&` would underline the next character
+            value = "&&".to_string();
+        } else if key == "word" {
+            value = "WORD".to_string();
+        } else if key == "sign" {
+            value = "i/u".to_string();
+        } else if key == "swap" {
+            value = "x↔y".to_string();
+        } else if key == "roll" {
+            value = "R↓".to_string();
+        } else if key == "drop" {
+            value = "DROP".to_string();
+            shortcut = Key::BackSpace;
+        } else if key == "enter" {
+            value = "ENTER".to_string();
         }
         
         let mut button = Button::new(
@@ -28,39 +67,65 @@ impl MyButton {
             value.as_str()
         );
 
-        let message = match c {
-            '0'..='9' => {
+        let message = match key {
+            "0" | "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9"
+            | "A" | "B" | "C" | "D" | "E" | "F" => {
                 button.set_color(button.color().darker());
                 button.set_color(button.color().darker());
                 button.set_color(button.color().darker());
                 button.set_label_color(Color::from_rgb(200, 200, 200));
-                MessageEmit::Number(c.to_digit(10).unwrap() as u32)
+                shortcut = Key::from_char(c.to_ascii_lowercase());
+                MessageEmit::Number(c.to_digit(16).unwrap())
             },
-            '+' | '-' | 'x' | '/' => {
+            "+" | "-" | "x" | "/" | "^" | "&" | "|" | "⊻" | "«" | "»" | "↺" | "↻" => {
                 if c == 'x' {
-                    key = Key::from_char('*');
+                    shortcut = Key::from_char('*');
                 }
                 MessageEmit::Operator(c)
             },
-            '=' => {
+            "=" | "enter" => {
                 button.set_color(Color::from_rgb(222,113,40));
                 button.set_label_color(Color::from_rgb(50, 50, 50));
                 button.set_selection_color(Color::from_rgb(107, 82, 65));
-                key = Key::Enter;
+                shortcut = Key::Enter;
                 MessageEmit::Equal
             },
-            'C' => MessageEmit::Clear,
-            'd' => MessageEmit::Delete,
-            '.' => MessageEmit::Dot,
-            '(' | ')' => MessageEmit::Parentheses(c),
-            _ => panic!("Unexpected button type: {}", c),
+            "AC" => MessageEmit::Clear,
+            "0x" | "0o" | "0b" => MessageEmit::RadixPrefix(key),
+            "d" => MessageEmit::Delete,
+            "swap" => MessageEmit::Swap,
+            "roll" => MessageEmit::RollDown,
+            "drop" => MessageEmit::Drop,
+            "." => MessageEmit::Dot,
+            "(" | ")" => MessageEmit::Parentheses(c),
+            "n" => MessageEmit::Negate,
+            "%" => MessageEmit::Percent,
+            "r" => MessageEmit::Root,
+            "~" => MessageEmit::Not,
+            "word" => MessageEmit::CycleWordSize,
+            "sign" => MessageEmit::ToggleSigned,
+            "drg" => MessageEmit::CycleAngleMode,
+            "frac" => MessageEmit::CycleFractionForm,
+            "MC" => MessageEmit::MemoryClear,
+            "MR" => MessageEmit::MemoryRecall,
+            "M+" => MessageEmit::MemoryAdd,
+            "M-" => MessageEmit::MemorySubtract,
+            "MS" => MessageEmit::MemoryStore,
+            "pi" => MessageEmit::Constant("π"),
+            name if Constant::from_name(name).is_some() => MessageEmit::Constant(name),
+            name if Function::from_name(name).is_some() => MessageEmit::Function(name),
+            _ => panic!("Unexpected button type: {}", key),
         };
 
-        button.set_shortcut(Shortcut::None | key);
+        button.set_shortcut(Shortcut::None | shortcut);
         button.visible_focus(false);
         button.emit(s, message);
 
-        MyButton {}
+        MyButton { button }
     }
-}
 
+    /// Removes the button from its window, e.g. when the keypad layout changes.
+    pub fn delete(self) {
+        WidgetBase::delete(self.button);
+    }
+}
@@ -1,70 +1,164 @@
-use fltk::{app::{self, App}, enums::{Align, Color, FrameType}, frame::Frame, image::PngImage, prelude::*, window::Window};
+use fltk::{app::{self, App}, browser::HoldBrowser, button::Button, draw, enums::{Align, CallbackTrigger, Color, Event, FrameType, Key, Shortcut}, frame::Frame, image::PngImage, menu::{MenuBar, MenuFlag}, prelude::*, text::{TextBuffer, TextEditor}, window::Window};
 use fltk_theme::{ColorTheme, color_themes};
-use regex::Regex;
 
-use crate::{button::MyButton, operations::parse_expression};
+use crate::{button::MyButton, engine::{CalculatorEngine, STORE_NAMES}, keypad::Layout, number::NumberMode, rpn::RpnEngine};
+use crate::session::{self, Session, SessionError};
+
+pub use crate::engine::MessageEmit;
 
 pub mod settings {
-    pub const WINDOW_WIDTH: i32 = 320;
-    pub const WINDOW_HEIGHT: i32 = 350;
-    pub const BUTTON_WIDTH: i32 = WINDOW_WIDTH / 4;
+    pub const BUTTON_WIDTH: i32 = 80;
     pub const BUTTON_HEIGHT: i32 = 50;
-    pub const RESULT_SCREEN_HEIGHT: i32 = WINDOW_HEIGHT - (BUTTON_HEIGHT * 5);
+    pub const RESULT_SCREEN_HEIGHT: i32 = 100;
     pub const RESULT_HEIGHT: i32 = 60;
     pub const MEMORY_OFFSET: i32 = 20;
     pub const MEMORY_HEIGHT: i32 = RESULT_SCREEN_HEIGHT - RESULT_HEIGHT - MEMORY_OFFSET;
     pub const RESULT_LABEL_SIZE: i32 = 28;
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum MessageEmit {
-    Number(u32),
-    Operator(char),
-    Equal,
-    Clear,
-    Delete,
-    Dot,
-    Parentheses(char),
+    pub const HISTORY_WIDTH: i32 = 200;
+    /// Height of the variables panel, under the history.
+    pub const VARIABLES_HEIGHT: i32 = 120;
+    pub const FUNCTIONS_DIALOG_WIDTH: i32 = 400;
+    pub const FUNCTIONS_DIALOG_HEIGHT: i32 = 300;
+    /// Space FLTK leaves between a right-aligned label and its frame.
+    pub const LABEL_MARGIN: i32 = 3;
 }
 
 pub struct CalculatorApp {
     calculator: App,
     main_window: Window,
     theme: ColorTheme,
+    /// Layout chosen in the View menu.
+    layout: Layout,
+    /// Layout of the buttons on screen, the programmer one in programmer
+    /// mode, and whether it has the RPN keys.
+    keypad: (Layout, bool),
+    menu: MenuBar,
     buttons: Vec<MyButton>,
+    engine: CalculatorEngine,
+    /// Takes the keys instead of `engine` while `rpn` is on.
+    rpn_engine: RpnEngine,
+    rpn: bool,
     result_output: Frame,
-    memory_output: Frame
+    memory_output: Frame,
+    /// Two lines per calculation: the expression, then the result.
+    history_panel: HoldBrowser,
+    /// One `name = value` line per variable.
+    variables_panel: HoldBrowser
+}
+
+impl Default for CalculatorApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Draws the caret as a bar before `label[caret..]`, the label being
+/// aligned right.
+fn draw_caret(frame: &mut Frame, caret: usize) {
+    let label = frame.label();
+    let after_caret = label.get(caret..).unwrap_or_default();
+
+    draw::set_font(frame.label_font(), frame.label_size());
+    let x = frame.x() + frame.w() - settings::LABEL_MARGIN - draw::width(after_caret) as i32;
+    let y = frame.y() + frame.h() / 2;
+    let half_height = frame.label_size() / 2;
+
+    draw::set_draw_color(frame.label_color());
+    draw::draw_line(x, y - half_height, x, y + half_height);
+}
+
+/// Hides a dialog and frees it along with its widgets.
+fn close_dialog(dialog: &mut Window) {
+    dialog.hide();
+    Window::delete(dialog.clone());
+}
+
+/// Every line of `panel`, as added.
+fn panel_lines(panel: &HoldBrowser) -> Vec<String> {
+    (1..=panel.size()).filter_map(|line| panel.text(line)).collect()
+}
+
+/// Engine with the session saved by the previous run, if any. A corrupt or
+/// unsupported session file is reported and ignored, as are its unreadable
+/// records and the variables and functions that no longer parse.
+fn restored_engine() -> CalculatorEngine {
+    let mut engine = CalculatorEngine::new();
+    let Some(path) = session::default_path() else {
+        return engine;
+    };
+
+    match Session::load(&path) {
+        Ok((session, skipped)) => {
+            let skipped = skipped.iter().map(|err| err.to_string());
+
+            for message in skipped.chain(engine.restore(session)) {
+                eprintln!("Error while restoring the session from {}: {message}", path.display());
+            }
+        },
+        Err(SessionError::Io(err)) if err.kind() == std::io::ErrorKind::NotFound => (),
+        Err(err) => eprintln!("Error while restoring the session from {}: {err}", path.display()),
+    }
+    engine
 }
 
 impl CalculatorApp {
     pub fn new() -> CalculatorApp {
+        let engine = restored_engine();
+        let rpn_engine = RpnEngine::with_settings_of(&engine);
+        let layout = Layout::default();
+        let keypad = layout.in_mode(engine.number_mode());
+        let (window_width, window_height) = keypad.window_size();
+
         CalculatorApp {
             calculator: app::App::default().with_scheme(app::Scheme::Gtk),
             main_window:  Window::default()
                 .with_label("Quantum Calculator 2000")
-                .with_size(settings::WINDOW_WIDTH, settings::WINDOW_HEIGHT)
+                .with_size(window_width + settings::HISTORY_WIDTH, window_height)
                 .center_screen(),
             theme: ColorTheme::new(color_themes::BLACK_THEME),
+            layout,
+            keypad: (keypad, false),
+            menu: MenuBar::new(0, 0, window_width + settings::HISTORY_WIDTH, settings::MEMORY_OFFSET, ""),
             buttons: vec![],
+            engine,
+            rpn_engine,
+            rpn: false,
             result_output: Frame::new(
                 0, 
                 settings::MEMORY_HEIGHT + settings::MEMORY_OFFSET, 
-                settings::WINDOW_WIDTH, 
+                window_width, 
                 settings::RESULT_HEIGHT, 
                 ""
             ).with_align(Align::Right | Align::Inside),
             memory_output: Frame::new(
                 0, 
                 settings::MEMORY_OFFSET, 
-                settings::WINDOW_WIDTH, 
+                window_width, 
                 settings::MEMORY_HEIGHT, 
                 ""
-            ).with_align(Align::Right | Align::Inside)
+            ).with_align(Align::Right | Align::Inside),
+            history_panel: HoldBrowser::new(
+                window_width,
+                settings::MEMORY_OFFSET,
+                settings::HISTORY_WIDTH,
+                window_height - settings::MEMORY_OFFSET - settings::VARIABLES_HEIGHT,
+                ""
+            ),
+            variables_panel: HoldBrowser::new(
+                window_width,
+                window_height - settings::VARIABLES_HEIGHT,
+                settings::HISTORY_WIDTH,
+                settings::VARIABLES_HEIGHT,
+                ""
+            )
         }
     }
 
     pub fn init_gui(&mut self) {
+        self.init_menu();
         self.init_outputs();
+        self.init_history_panel();
+        self.init_variables_panel();
         self.init_buttons();
 
         if let Ok(icon) = PngImage::load("./src/assets/logo.png") {
@@ -83,433 +177,268 @@ impl CalculatorApp {
 
         while self.calculator.wait() {
             if let Some(msg) = r.recv() {
-                let mut output = self.get_trimmed_output();
-
                 match msg {
-                    MessageEmit::Number(num) => output = self.handle_message_number(output, num),
-                    MessageEmit::Clear => output = self.handle_message_clear(),
-                    MessageEmit::Delete => output = self.handle_message_delete(output),
-                    MessageEmit::Dot => output = self.handle_message_dot(output),
-                    MessageEmit::Operator(op) => output = self.handle_message_operator(output, op),
-                    MessageEmit::Parentheses(par) => output = self.handle_message_parentheses(output, par),
-                    MessageEmit::Equal => output = self.handle_message_equal(output)
+                    MessageEmit::SwitchLayout(layout) => self.switch_layout(layout),
+                    MessageEmit::ToggleRpn => self.rpn = !self.rpn,
+                    MessageEmit::Copy if self.rpn => app::copy(&self.rpn_engine.copy_text()),
+                    MessageEmit::Copy => app::copy(&self.engine.copy_text()),
+                    MessageEmit::EditFunctions => self.edit_functions(),
+                    msg @ MessageEmit::DefineFunctions(_) => self.engine.handle_message(msg),
+                    msg if msg.is_setting() => {
+                        self.rpn_engine.handle_message(msg.clone());
+                        self.engine.handle_message(msg);
+                    },
+                    msg if self.rpn => self.rpn_engine.handle_message(msg),
+                    msg => self.engine.handle_message(msg)
                 }
-                output = self.format_result_output(output);
-                self.result_output.set_label(&output);
+                self.refresh_keypad();
+                self.refresh_outputs();
             }
         }
-                
+
+        self.save_session();
     }
 
-    fn handle_message_equal(&mut self, mut output: String) -> String {
-        output = output
-            .trim_end_matches(|c| c == '(' || c == '+' || c == '-' || c == '/' || c == 'x' || c == '.' || c == ' ')
-            .to_string();
+    /// Opens a modal dialog with one function definition per line, which
+    /// replace the defined functions on OK.
+    fn edit_functions(&mut self) {
+        let (s, _r) = app::channel::<MessageEmit>();
+        let (width, height) = (settings::FUNCTIONS_DIALOG_WIDTH, settings::FUNCTIONS_DIALOG_HEIGHT);
+        let (button_width, button_height) = (settings::BUTTON_WIDTH, settings::BUTTON_HEIGHT / 2);
+        let mut buffer = TextBuffer::default();
+        buffer.set_text(&self.engine.function_definitions());
+
+        let mut dialog = Window::default().with_size(width, height).with_label("Functions");
+        let mut editor = TextEditor::new(10, 10, width - 20, height - button_height - 30, "");
+        editor.set_buffer(buffer.clone());
+        let mut ok = Button::new(width - 2 * button_width - 20, height - button_height - 10, button_width, button_height, "OK");
+        let mut cancel = Button::new(width - button_width - 10, height - button_height - 10, button_width, button_height, "Cancel");
+        dialog.end();
+        dialog.make_modal(true);
+        dialog.show();
+
+        let mut ok_dialog = dialog.clone();
+        ok.set_callback(move |_| {
+            s.send(MessageEmit::DefineFunctions(buffer.text()));
+            close_dialog(&mut ok_dialog);
+        });
+        let mut cancel_dialog = dialog.clone();
+        cancel.set_callback(move |_| close_dialog(&mut cancel_dialog));
+        // Closing the window cancels too
+        dialog.set_callback(close_dialog);
+    }
 
-        let opened_par_count = output.chars().filter(|c| *c == '(').count();
-        let mut closed_par_count = output.chars().filter(|c| *c == ')').count();
+    fn save_session(&self) {
+        let Some(path) = session::default_path() else {
+            return;
+        };
 
-        while closed_par_count < opened_par_count {
-            output.push(')');
-            closed_par_count += 1;
+        if let Err(err) = self.engine.session().save(&path) {
+            eprintln!("Error while saving the session to {}: {err}", path.display());
         }
-
-        let result: f64 = parse_expression(&output).value;
-        // println!()
-
-        output = self.format_result_output(output);
-        output.push_str("=  ");
-        self.memory_output.set_label(&output);
-
-        result.to_string()
     }
 
-    fn handle_message_parentheses(&self, mut output: String, par: char) -> String {
-        if par == ')' {
-            let opened_par_count = output.chars().filter(|c| *c == '(').count();
-            let closed_par_count = output.chars().filter(|c| *c == ')').count();
-            
-            if opened_par_count > closed_par_count {
-                if let Some(last_char) = output.chars().last() {
-                    if self.is_an_operator(last_char) {
-                        output.pop();
-                    }
-                }
-                output.push(par);
-            }
+    /// The stack levels take the place of the memory line in RPN mode.
+    fn refresh_outputs(&mut self) {
+        let (display, caret, memory_line) = if self.rpn {
+            (self.rpn_engine.display(), self.rpn_engine.caret(), self.rpn_engine.memory_line())
         } else {
-            if output == "0" {
-                output = "(".to_string();
-            } else {
-                output.push(par);
-            }
-        }
+            (self.engine.display().to_string(), self.engine.caret(), self.engine.memory_line())
+        };
 
-        output
-    }
+        self.result_output.set_label(&display);
+        self.result_output.draw(move |frame| draw_caret(frame, caret));
+        self.memory_output.set_label(&memory_line);
 
-    fn handle_message_dot(&self, mut output: String) -> String {
-        if let Some(last_char) = output.chars().last() {
-            if self.is_an_operator(last_char) {
-                output.insert_str(output.len(), ".");
-            } else {
-                let last_elem: String = output
-                    .split(|c: char| c == 'x' || c == '+' || c == '/' || c == '-')
-                    .last()
-                    .unwrap()
-                    .to_string();
-            
-                if last_elem
-                    .chars()
-                    .filter(|c| *c == '.')
-                    .count() == 0 
-                {
-                    output.insert(output.len(), '.');
-                }
+        // Compared line by line, the count staying the same once the
+        // oldest calculations are dropped
+        let calculations: Vec<String> = self.engine.calculations()
+            .entries()
+            .iter()
+            .flat_map(|entry| [format!("{} =", entry.expression), format!("@r@b{}", entry.result)])
+            .collect();
+
+        if panel_lines(&self.history_panel) != calculations {
+            self.history_panel.clear();
+            for line in &calculations {
+                self.history_panel.add(line);
             }
+            self.history_panel.bottom_line(self.history_panel.size());
         }
 
-        output
-    }
+        let variables: Vec<String> = self.engine.variables()
+            .into_iter()
+            .map(|(name, value)| format!("{name} = {value}"))
+            .collect();
 
-    fn handle_message_operator(&self, mut output: String, op: char) -> String {
-        if let Some(last_char) = output.chars().last() {
-            if self.is_an_operator(last_char) {
-                output.pop();
-            } else if last_char == '(' {
-                output.push('0');
+        if panel_lines(&self.variables_panel) != variables {
+            self.variables_panel.clear();
+            for line in &variables {
+                self.variables_panel.add(line);
             }
-            output = format!("{output}{op}");
         }
-
-        output
     }
 
-    fn handle_message_number(&self, mut output: String, num: u32) -> String {
-        let output_len = output.len();
-                        
-        if output == "0" {
-            output = format!("{num}");
-        } else if output_len <= 100 { // A CHANGER
-            output.insert(output_len, char::from_digit(num, 10).unwrap());
-        };
-
-        output
+    fn switch_layout(&mut self, layout: Layout) {
+        self.layout = layout;
     }
 
-    fn handle_message_clear(&mut self) -> String {
-        self.memory_output.set_label("");
-        "0".to_string()
-    }
+    /// Rebuilds the keypad when the layout, the number mode or RPN mode
+    /// changed which one is shown, and resizes the window around it.
+    fn refresh_keypad(&mut self) {
+        let keypad = self.layout.in_mode(self.engine.number_mode());
 
-    fn handle_message_delete(&self, mut output: String) -> String {
-        if output != "0" {
-            output.pop();
+        if (keypad, self.rpn) == self.keypad {
+            return;
         }
 
-        if output.is_empty() {
-            output = "0".to_string();
+        for button in self.buttons.drain(..) {
+            button.delete();
         }
-
-        output
+        self.keypad = (keypad, self.rpn);
+
+        let (window_width, window_height) = keypad.window_size();
+
+        self.main_window.set_size(window_width + settings::HISTORY_WIDTH, window_height);
+        self.menu.set_size(window_width + settings::HISTORY_WIDTH, settings::MEMORY_OFFSET);
+        self.history_panel.resize(
+            window_width,
+            settings::MEMORY_OFFSET,
+            settings::HISTORY_WIDTH,
+            window_height - settings::MEMORY_OFFSET - settings::VARIABLES_HEIGHT
+        );
+        self.variables_panel.resize(
+            window_width,
+            window_height - settings::VARIABLES_HEIGHT,
+            settings::HISTORY_WIDTH,
+            settings::VARIABLES_HEIGHT
+        );
+        self.result_output.set_size(window_width, settings::RESULT_HEIGHT);
+        self.memory_output.set_size(window_width, settings::MEMORY_HEIGHT);
+
+        self.main_window.begin();
+        self.init_buttons();
+        self.main_window.end();
+        self.main_window.redraw();
     }
 
-    fn get_trimmed_output(&self) -> String {
-        self.result_output.label().replace(' ', "")
-    }    
-
-    fn format_result_output(&mut self, raw_output: String) -> String {
-        let mut formated_output = String::from("");
-        let segments: Vec<&str> = raw_output
-            .split_inclusive(|c: char| c == 'x' || c == '+' || c == '/' || c == '-' || c == '(' || c == ')')
-            .collect();
+    fn init_menu(&mut self) {
+        let (s, _r) = app::channel::<MessageEmit>();
 
-        for elem in segments {
-            let trimmed_number = elem.trim_end_matches(|c| c == 'x' || c == '+' || c == '/' || c == '-' || c == '(' || c == ')');
-
-            // Add spaces to integer part if needed every 3 decimals
-            formated_output.push_str(&self.format_number(trimmed_number));
-            // Add spaces around operators
-            formated_output.push_str(&self.add_spaces_around_operators(elem));
-        }
-
-        self.replace_patterns_and_add_last_space(formated_output)
-    }
-
-    fn replace_patterns_and_add_last_space(&self, mut formated_output: String) -> String {
-        if let Some(c) = formated_output.chars().last() {
-            if c != ' ' {
-                formated_output.push(' ');
-            }
-        } 
+        self.menu.add_emit("Edit/Undo", Shortcut::Ctrl | 'z', MenuFlag::Normal, s.clone(), MessageEmit::Undo);
+        self.menu.add_emit("Edit/Redo", Shortcut::Ctrl | Shortcut::Shift | 'z', MenuFlag::Normal, s.clone(), MessageEmit::Redo);
+        self.menu.add_emit("Edit/Copy", Shortcut::Ctrl | 'c', MenuFlag::Normal, s.clone(), MessageEmit::Copy);
 
-        formated_output = formated_output.replace("  ", " ");
-        formated_output = formated_output.replace(" .", " 0.");
-        formated_output = formated_output.replace("(.", "(0.");
-        formated_output = formated_output.replace(") (", ") x (");
-        formated_output = formated_output.replace("()", "(1)");
+        // The clipboard text comes back to the display as a paste event
+        let result_output = self.result_output.clone();
+        self.menu.add("Edit/Paste", Shortcut::Ctrl | 'v', MenuFlag::Normal, move |_| app::paste_text(&result_output));
 
-        let mut regex = Regex::new(r"(?<n>\d) \(").unwrap();
-        formated_output = regex.replace_all(&formated_output, r"$n x (").to_string();
-        
-        regex = Regex::new(r"\) (?<n>\d)").unwrap();
-        formated_output = regex.replace_all(&formated_output, r") x $n").to_string();
-
-        formated_output
-    }
-
-    fn add_spaces_around_operators(&self, segment: &str) -> String {
-        let last_char: char = segment.chars().last().unwrap();
+        let carets = [
+            ("Edit/Caret left", Key::Left, MessageEmit::CaretLeft),
+            ("Edit/Caret right", Key::Right, MessageEmit::CaretRight),
+            ("Edit/Caret to start", Key::Home, MessageEmit::CaretHome),
+            ("Edit/Caret to end", Key::End, MessageEmit::CaretEnd),
+        ];
 
-        if self.is_an_operator(last_char) {
-            format!(" {last_char} ")
-        } else if last_char == '(' {
-            format!(" {last_char}")
-        } else if last_char == ')' {
-            format!("{last_char} ")
-        } else {
-            "".to_string()
+        for (label, key, msg) in carets {
+            self.menu.add_emit(label, Shortcut::None | key, MenuFlag::Normal, s.clone(), msg);
         }
-    }
 
-    fn format_number(&self, number: &str) -> String {
-        match number.split_once('.') {
-            Some((integer_part, decimal_part)) => {
-                let formated_integer_part: String = self.format_integer_part(integer_part);
+        let layouts = [
+            ("View/Basic", '1', Layout::Basic),
+            ("View/Scientific", '2', Layout::Scientific),
+        ];
 
-                format!("{formated_integer_part}.{decimal_part}")
-            },
-            None => {
-                let formated_integer_part: String = self.format_integer_part(number);
+        for (label, key, layout) in layouts {
+            let mut flag = MenuFlag::Radio;
 
-                format!("{formated_integer_part}")
-            } 
+            if layout == self.layout {
+                flag |= MenuFlag::Value;
+            }
+            self.menu.add_emit(label, Shortcut::Ctrl | key, flag, s.clone(), MessageEmit::SwitchLayout(layout));
         }
-    }
 
-    fn format_integer_part(&self, integer_part: &str) -> String {
-        let mut formated_integer: String = String::from(integer_part);
-        let mut add_space_index = formated_integer.len() as i8 - 3;
+        for mode in NumberMode::ALL {
+            let mut flag = MenuFlag::Radio;
 
-        while add_space_index > 0 {
-            formated_integer.insert(add_space_index as usize, ' ');
-            add_space_index -= 3;
-        } 
-
-        formated_integer
+            if mode == self.engine.number_mode() {
+                flag |= MenuFlag::Value;
+            }
+            let label = format!("Settings/Numbers/{}", mode.name());
+            self.menu.add_emit(&label, Shortcut::None, flag, s.clone(), MessageEmit::SetNumberMode(mode));
+        }
+        self.menu.add_emit("Settings/Fraction form", Shortcut::Ctrl | 'f', MenuFlag::Normal, s.clone(), MessageEmit::CycleFractionForm);
+        self.menu.add_emit("Settings/Word size", Shortcut::None, MenuFlag::Normal, s.clone(), MessageEmit::CycleWordSize);
+        self.menu.add_emit("Settings/Signed or unsigned", Shortcut::None, MenuFlag::Normal, s.clone(), MessageEmit::ToggleSigned);
+        self.menu.add_emit("Settings/RPN", Shortcut::Ctrl | 'r', MenuFlag::Toggle, s.clone(), MessageEmit::ToggleRpn);
+        self.menu.add_emit("History/Clear", Shortcut::None, MenuFlag::Normal, s.clone(), MessageEmit::ClearHistory);
+        self.menu.add_emit("Functions/Edit...", Shortcut::Ctrl | 'e', MenuFlag::Normal, s.clone(), MessageEmit::EditFunctions);
+        self.menu.add_emit("Memory/Ans", Shortcut::Ctrl | 'a', MenuFlag::Normal, s.clone(), MessageEmit::Answer);
+
+        for name in STORE_NAMES {
+            self.menu.add_emit(&format!("Memory/Store/{name}"), Shortcut::None, MenuFlag::Normal, s.clone(), MessageEmit::StoreTo(name));
+        }
+        for name in STORE_NAMES {
+            self.menu.add_emit(&format!("Memory/Recall/{name}"), Shortcut::None, MenuFlag::Normal, s.clone(), MessageEmit::RecallFrom(name));
+        }
     }
 
     fn init_outputs(&mut self) {
         self.result_output.set_frame(FrameType::FlatBox);
         self.result_output.set_label_color(Color::from_rgb(200, 200, 200));
         self.result_output.set_label_size(settings::RESULT_LABEL_SIZE);
-        self.result_output.set_label("0 ");
         self.memory_output.set_frame(FrameType::FlatBox);
-    }
 
-    fn init_buttons(&mut self) {
-        let sequence = "()Cd789/456x123-.0=+";
-        let mut index: usize = 0;
-        let step_x: i32 = settings::WINDOW_WIDTH / 4;
-        let step_y: i32 = settings::BUTTON_HEIGHT;
-        let offset_y: i32 = settings::WINDOW_HEIGHT - (settings::BUTTON_HEIGHT * 5);
-    
-        for y in 0..5 {
-            for x in 0..4 {
-                if index >= sequence.chars().count() {
-                    break;
-                }
-    
-                let pos_x = x * step_x;
-                let pos_y = y * step_y + offset_y;
-                let character = sequence.chars().nth(index).unwrap();
-                
-                self.buttons.push(MyButton::new(character, (pos_x, pos_y)));
-                index += 1;
+        let (s, _r) = app::channel::<MessageEmit>();
+        self.result_output.handle(move |_, event| {
+            if event == Event::Paste {
+                s.send(MessageEmit::Paste(app::event_text()));
+                return true;
             }
-        }
-    }
-
-    fn is_an_operator(&self, op: char) -> bool {
-        op == 'x' || op == '+' || op == '-' || op == '/'
-    }
-}
-
-
-#[cfg(test)]
-mod tests {
-    use fltk::prelude::WidgetExt;
-    use super::CalculatorApp;
-
-    #[test]
-    fn get_trimmed_output_tests() {
-        let mut calculator = CalculatorApp::new();
-
-        let data = vec![
-            (String::from("1 123 456 + 156 - 15 / .23 x 9 156 + 0 "), String::from("1123456+156-15/.23x9156+0")),
-            (String::from("0 + 5 - 1 123 / "), String::from("0+5-1123/")),
-            (String::from("0 "), String::from("0")),
-        ];
-
-        for (input, expected_output) in data {
-            calculator.result_output.set_label(&input);
-            
-            assert_eq!(calculator.get_trimmed_output(), expected_output);
-        }
+            false
+        });
+        self.refresh_outputs();
     }
 
-    #[test]
-    fn handle_message_number_tests() {
-        let calculator = CalculatorApp::new();
-
-        let data = vec![
-            (String::from("0"), 1, String::from("1")),
-            (String::from("1"), 1, String::from("11")),
-            (String::from("15+"), 1, String::from("15+1")),
-            (String::from("15-"), 1, String::from("15-1")),
-            (String::from("15."), 1, String::from("15.1")),
-            (String::from("0"), 0, String::from("0")),
-        ];
-
-        for (input, num, expected_output) in data {
-            let output = calculator.handle_message_number(input, num);
-            
-            assert_eq!(output, expected_output);
-        }
-    }
-    
-    #[test]
-    fn handle_message_clear_tests() {
-        let mut calculator = CalculatorApp::new();
-
-        let data = vec![
-            (String::from("0"), String::from("0")),
-            (String::from("1564"), String::from("0")),
-            (String::from("15+"), String::from("0")),
-            (String::from("0.226x"), String::from("0")),
-        ];
+    /// Clicking an expression line recalls the expression, a result line the result.
+    fn init_history_panel(&mut self) {
+        let (s, _r) = app::channel::<MessageEmit>();
 
-        for (input, expected_output) in data {
-            calculator.result_output.set_label(&input);
-            
-            assert_eq!(calculator.handle_message_clear(), expected_output);
-        }
-    }
-    
-    #[test]
-    fn handle_message_delete_tests() {
-        let calculator = CalculatorApp::new();
-
-        let data = vec![
-            (String::from("0"), String::from("0")),
-            (String::from("1564"), String::from("156")),
-            (String::from("15+"), String::from("15")),
-            (String::from("0.226x"), String::from("0.226")),
-            (String::from("0.226"), String::from("0.22")),
-            (String::from("0.2"), String::from("0.")),
-            (String::from("1"), String::from("0")),
-        ];
+        self.history_panel.set_trigger(CallbackTrigger::ReleaseAlways);
+        self.history_panel.set_callback(move |panel| {
+            let line = panel.value();
 
-        for (input, expected_output) in data {
-            assert_eq!(calculator.handle_message_delete(input), expected_output);
-        }
-    }
-    
-    #[test]
-    fn handle_message_dot_tests() {
-        let calculator = CalculatorApp::new();
-
-        let data = vec![
-            (String::from("0"), String::from("0.")),
-            (String::from("1564"), String::from("1564.")),
-            (String::from("15+"), String::from("15+.")),
-            (String::from("0.226x"), String::from("0.226x.")),
-            (String::from("0.226"), String::from("0.226")),
-            (String::from("10.15+0.226"), String::from("10.15+0.226")),
-            (String::from("10.15+0.226/1."), String::from("10.15+0.226/1.")),
-            (String::from("10.15+26"), String::from("10.15+26.")),
-            (String::from("0.2"), String::from("0.2")),
-            (String::from("1"), String::from("1.")),
-        ];
+            if line > 0 {
+                let index = (line as usize - 1) / 2;
 
-        for (input, expected_output) in data {
-            assert_eq!(calculator.handle_message_dot(input), expected_output);
-        }
+                if line % 2 == 1 {
+                    s.send(MessageEmit::RecallExpression(index));
+                } else {
+                    s.send(MessageEmit::RecallResult(index));
+                }
+            }
+        });
     }
 
-    #[test]
-    fn handle_message_operator_tests() {
-        let calculator = CalculatorApp::new();
-
-        let data = vec![
-            (String::from("0"), '+', String::from("0+")),
-            (String::from("156+"), '-', String::from("156-")),
-            (String::from("15+"), 'x', String::from("15x")),
-            (String::from("0.226/"), '/', String::from("0.226/")),
-            (String::from("0.226"), '+', String::from("0.226+")),
-            (String::from("0."), '+', String::from("0.+")),
-            (String::from("1"), '+', String::from("1+")),
-        ];
+    /// Clicking a variable inserts its name.
+    fn init_variables_panel(&mut self) {
+        let (s, _r) = app::channel::<MessageEmit>();
 
-        for (input, op, expected_output) in data {
-            assert_eq!(calculator.handle_message_operator(input, op), expected_output);
-        }
-    }
-
-    #[test]
-    fn format_result_output_tests() {
-        let mut calculator = CalculatorApp::new();
-
-        let data = vec![
-            (String::from("0"), String::from("0 ")),
-            (String::from("156+"), String::from("156 + ")),
-            (String::from("15-"), String::from("15 - ")),
-            (String::from("15x"), String::from("15 x ")),
-            (String::from("1(15"), String::from("1 x (15 ")),
-            (String::from("15)1"), String::from("15) x 1 ")),
-            (String::from("0.226/"), String::from("0.226 / ")),
-            (String::from("0.226/15+1-"), String::from("0.226 / 15 + 1 - ")),
-            (String::from("(0.226/(15+1))-"), String::from(" (0.226 / (15 + 1) ) - ")),
-            (String::from("0.226666666"), String::from("0.226666666 ")),
-            (String::from("0.0"), String::from("0.0 ")),
-            (String::from("()"), String::from(" (1) ")),
-            (String::from("1000000"), String::from("1 000 000 ")),
-            (String::from("1000000.55"), String::from("1 000 000.55 ")),
-            (String::from("1000000.55555555"), String::from("1 000 000.55555555 ")),
-            (String::from("1000000.55555555+.155555"), String::from("1 000 000.55555555 + 0.155555 ")),
-            (String::from("100000.55555555+.155555/123456+1500.1568"), String::from("100 000.55555555 + 0.155555 / 123 456 + 1 500.1568 ")),
-            (String::from("100000.55555555+(.155555/123456)+1500.1568"), String::from("100 000.55555555 + (0.155555 / 123 456) + 1 500.1568 ")),
-        ];
+        self.variables_panel.set_trigger(CallbackTrigger::ReleaseAlways);
+        self.variables_panel.set_callback(move |panel| {
+            let name = panel.selected_text().and_then(|line| line.split(" = ").next().map(str::to_string));
 
-        for (input, expected_output) in data {
-            let output = calculator.format_result_output(input);
-
-            assert_eq!(output, expected_output);
-        }
+            if let Some(name) = name {
+                s.send(MessageEmit::RecallVariable(name));
+            }
+        });
     }
 
-    #[test]
-    fn handle_message_parentheses_tests() {
-        let calculator = CalculatorApp::new();
-
-        let data = vec![
-            (String::from("0"), '(', String::from("(")),
-            (String::from("156+"), '(', String::from("156+(")),
-            (String::from("15-"), '(', String::from("15-(")),
-            (String::from("(15-"), ')', String::from("(15)")),
-            (String::from("(15x"), '(', String::from("(15x(")),
-            (String::from("0.226/"), '(', String::from("0.226/(")),
-            (String::from("0.226/15+1-"), ')', String::from("0.226/15+1-")),
-            (String::from("(0.226666666"), ')', String::from("(0.226666666)")),
-            (String::from("((0.0"), ')', String::from("((0.0)")),
-            (String::from("(1000000)"), ')', String::from("(1000000)")),
-            (String::from("((1000000.55"), ')', String::from("((1000000.55)")),
-            (String::from("(2-"), ')', String::from("(2)")),
-
-        ];
+    fn init_buttons(&mut self) {
+        let (keypad, rpn) = self.keypad;
 
-        for (input, par, expected_output) in data {
-            assert_eq!(calculator.handle_message_parentheses(input, par), expected_output);
+        for (key, position) in keypad.key_positions(rpn) {
+            self.buttons.push(MyButton::new(key, position));
         }
     }
 }
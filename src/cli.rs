@@ -0,0 +1,196 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::ast::Context;
+use crate::operations::evaluate_statement;
+
+pub const USAGE: &str = "Usage: basic-desktop-calculator [-e EXPRESSION]... | - | --repl
+
+Without arguments, opens the calculator window, or evaluates the lines
+of stdin when a pipe or a file is redirected to it.
+
+Options:
+  -e, --eval EXPRESSION  Evaluates EXPRESSION and prints the result
+  -                      Evaluates the lines of stdin, one per line
+      --repl             Reads expressions in the terminal, one per line
+  -h, --help             Prints this message";
+
+/// What the binary was asked to do.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Gui,
+    /// The expressions of every `-e`, in order.
+    Evaluate(Vec<String>),
+    /// One expression per line of stdin.
+    EvaluateStdin,
+    Repl,
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ArgsError {
+    MissingExpression(String),
+    UnknownArgument(String),
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgsError::MissingExpression(option) => write!(f, "Missing expression after '{option}'"),
+            ArgsError::UnknownArgument(arg) => write!(f, "Unknown argument '{arg}'"),
+        }
+    }
+}
+
+impl std::error::Error for ArgsError {}
+
+impl Command {
+    /// `args` leaves out the program name. Without arguments, input piped
+    /// on stdin is evaluated rather than opening the window.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I, stdin_is_piped: bool) -> Result<Command, ArgsError> {
+        let mut args = args.into_iter();
+        let mut expressions: Vec<String> = vec![];
+        let mut repl = false;
+        let mut read_stdin = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(Command::Help),
+                "--repl" => repl = true,
+                "-" => read_stdin = true,
+                "-e" | "--eval" => expressions.push(args.next().ok_or(ArgsError::MissingExpression(arg))?),
+                _ => return Err(ArgsError::UnknownArgument(arg)),
+            }
+        }
+
+        if repl {
+            Ok(Command::Repl)
+        } else if !expressions.is_empty() {
+            Ok(Command::Evaluate(expressions))
+        } else if read_stdin || stdin_is_piped {
+            Ok(Command::EvaluateStdin)
+        } else {
+            Ok(Command::Gui)
+        }
+    }
+}
+
+/// Whether stdin is a pipe or a regular file. A terminal, or `/dev/null`
+/// as when started from a desktop entry, leaves the window to open.
+#[cfg(unix)]
+pub fn stdin_is_piped() -> bool {
+    use std::os::fd::AsFd;
+    use std::os::unix::fs::FileTypeExt;
+
+    io::stdin()
+        .as_fd()
+        .try_clone_to_owned()
+        .and_then(|fd| std::fs::File::from(fd).metadata())
+        .is_ok_and(|metadata| metadata.file_type().is_fifo() || metadata.is_file())
+}
+
+#[cfg(not(unix))]
+pub fn stdin_is_piped() -> bool {
+    use std::io::IsTerminal;
+
+    !io::stdin().is_terminal()
+}
+
+/// Prints the result of each expression to `out`, or its error to `err`.
+/// Spaces are ignored, as on the display, and variables assigned or
+/// functions defined by one expression are known to the next ones, a
+/// definition printing nothing. Returns whether all of them evaluated.
+pub fn evaluate_all<I, W, E>(expressions: I, out: &mut W, err: &mut E) -> io::Result<bool>
+where
+    I: IntoIterator<Item = String>,
+    W: Write,
+    E: Write,
+{
+    let mut succeeded = true;
+    let mut context = Context::default();
+
+    for expression in expressions {
+        let input: String = expression.chars().filter(|c| !c.is_whitespace()).collect();
+
+        match evaluate_statement(&input, &mut context) {
+            Ok(Some(result)) => writeln!(out, "{}", result.value)?,
+            Ok(None) => (),
+            Err(parse_error) => {
+                writeln!(err, "{}: {parse_error}", expression.trim())?;
+                succeeded = false;
+            },
+        }
+    }
+
+    Ok(succeeded)
+}
+
+/// Evaluates every line of `input` that is not blank.
+pub fn evaluate_lines<R, W, E>(input: R, out: &mut W, err: &mut E) -> io::Result<bool>
+where
+    R: BufRead,
+    W: Write,
+    E: Write,
+{
+    let lines = input.lines().collect::<io::Result<Vec<String>>>()?;
+
+    evaluate_all(lines.into_iter().filter(|line| !line.trim().is_empty()), out, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate_all, evaluate_lines, ArgsError, Command};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn from_args_tests() {
+        let data: Vec<(Vec<String>, bool, Result<Command, ArgsError>)> = vec![
+            (args(&[]), false, Ok(Command::Gui)),
+            (args(&[]), true, Ok(Command::EvaluateStdin)),
+            (args(&["-"]), false, Ok(Command::EvaluateStdin)),
+            (args(&["-e", "2x(3+4)"]), false, Ok(Command::Evaluate(args(&["2x(3+4)"])))),
+            (args(&["-e", "1", "--eval", "2"]), true, Ok(Command::Evaluate(args(&["1", "2"])))),
+            (args(&["--help", "-e"]), true, Ok(Command::Help)),
+            (args(&["--repl"]), true, Ok(Command::Repl)),
+            (args(&["--repl"]), false, Ok(Command::Repl)),
+            (args(&["-e"]), true, Err(ArgsError::MissingExpression(String::from("-e")))),
+            (args(&["2+2"]), true, Err(ArgsError::UnknownArgument(String::from("2+2")))),
+        ];
+
+        for (input, stdin_is_piped, expected_command) in data {
+            assert_eq!(Command::from_args(input.clone(), stdin_is_piped), expected_command, "Failed test for {input:?}");
+        }
+    }
+
+    #[test]
+    fn evaluate_tests() {
+        let data: Vec<(&str, bool, &str, &str)> = vec![
+            ("1+1\n", true, "2\n", ""),
+            ("2x(3+4)\n\n 1 000 / 8 \n", true, "14\n125\n", ""),
+            ("1+\n3x3\n", false, "9\n", "1+: Unexpected end at 2\n"),
+            ("1/0", false, "", "1/0: Division by zero\n"),
+            ("rate = 0.07\n1200 x rate\n", true, "0.07\n84\n", ""),
+            ("2 x rate\n", false, "", "2 x rate: Undefined variable 'rate'\n"),
+            ("f(x, y) = x^2 + y\nf(3, 4)\n", true, "13\n", ""),
+            ("f(1)\n", false, "", "f(1): Undefined function 'f'\n"),
+            ("", true, "", ""),
+        ];
+
+        for (input, expected_success, expected_out, expected_err) in data {
+            let mut out: Vec<u8> = vec![];
+            let mut err: Vec<u8> = vec![];
+
+            let succeeded = evaluate_lines(input.as_bytes(), &mut out, &mut err).unwrap();
+            assert_eq!(succeeded, expected_success, "Failed test for {input:?}");
+            assert_eq!(String::from_utf8(out).unwrap(), expected_out);
+            assert_eq!(String::from_utf8(err).unwrap(), expected_err);
+        }
+
+        let mut out: Vec<u8> = vec![];
+        assert!(evaluate_all(args(&["2^10", "√16"]), &mut out, &mut std::io::sink()).unwrap());
+        assert_eq!(String::from_utf8(out).unwrap(), "1024\n4\n");
+    }
+}
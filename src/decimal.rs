@@ -0,0 +1,184 @@
+use std::fmt;
+use std::num::NonZeroU64;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::str::FromStr;
+
+use num_bigint::BigInt;
+use bigdecimal::{BigDecimal, FromPrimitive, RoundingMode, ToPrimitive, Zero};
+
+/// Significant digits kept in results unless the context says otherwise.
+pub const DEFAULT_PRECISION: u64 = 32;
+
+/// Digits an `f64` result is trusted to, for functions computed in binary.
+const FLOAT_DIGITS: u64 = 15;
+
+/// Results past `10^MAX_MAGNITUDE` (or below its inverse) are out of range.
+pub(crate) const MAX_MAGNITUDE: i64 = 100;
+
+/// A base-10 number, so that `0.1+0.2` is exactly `0.3`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Default)]
+pub struct Decimal(BigDecimal);
+
+impl Decimal {
+    pub fn zero() -> Decimal {
+        Decimal(BigDecimal::zero())
+    }
+
+    /// Keeps the 15 significant digits of `value` an `f64` is accurate to,
+    /// so that `0.49999999999999994` comes back as `0.5`.
+    pub fn from_f64(value: f64) -> Option<Decimal> {
+        if !value.is_finite() {
+            return None;
+        }
+        BigDecimal::from_f64(value).map(|value| Decimal(value).round(FLOAT_DIGITS))
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.0.to_f64().unwrap_or(f64::NAN)
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.is_integer() {
+            self.0.to_i64()
+        } else {
+            None
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.0.is_integer()
+    }
+
+    pub fn is_out_of_range(&self) -> bool {
+        !self.is_zero() && self.0.order_of_magnitude().abs() > MAX_MAGNITUDE
+    }
+
+    /// Digits and scale, the value being `digits x 10^-scale`.
+    pub fn as_bigint_and_scale(&self) -> (BigInt, i64) {
+        self.0.as_bigint_and_exponent()
+    }
+
+    pub fn abs(&self) -> Decimal {
+        Decimal(self.0.abs())
+    }
+
+    /// Rounds half-even to `precision` significant digits.
+    pub fn round(&self, precision: u64) -> Decimal {
+        Decimal(context(precision).round_decimal_ref(&self.0).normalized())
+    }
+
+    /// Returns `None` when dividing by zero.
+    pub fn checked_div(&self, divisor: &Decimal, precision: u64) -> Option<Decimal> {
+        if divisor.is_zero() {
+            return None;
+        }
+        Some(Decimal(&self.0 * divisor.0.inverse_with_context(&context(precision))).round(precision))
+    }
+
+    /// Returns `None` for negative numbers.
+    pub fn sqrt(&self, precision: u64) -> Option<Decimal> {
+        self.0.sqrt_with_context(&context(precision)).map(|value| Decimal(value).round(precision))
+    }
+
+    pub fn powi(&self, exponent: i64, precision: u64) -> Decimal {
+        Decimal(self.0.powi_with_context(exponent, &context(precision))).round(precision)
+    }
+}
+
+fn context(precision: u64) -> bigdecimal::Context {
+    let precision = NonZeroU64::new(precision).unwrap_or(NonZeroU64::MIN);
+
+    bigdecimal::Context::new(precision, RoundingMode::HalfEven)
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Decimal {
+        Decimal(BigDecimal::from(value))
+    }
+}
+
+impl From<BigInt> for Decimal {
+    fn from(value: BigInt) -> Decimal {
+        Decimal(BigDecimal::from(value))
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = bigdecimal::ParseBigDecimalError;
+
+    fn from_str(literal: &str) -> Result<Decimal, Self::Err> {
+        BigDecimal::from_str(literal).map(|value| Decimal(value.normalized()))
+    }
+}
+
+impl Add for Decimal {
+    type Output = Decimal;
+
+    fn add(self, rhs: Decimal) -> Decimal {
+        Decimal(self.0 + rhs.0)
+    }
+}
+
+impl Sub for Decimal {
+    type Output = Decimal;
+
+    fn sub(self, rhs: Decimal) -> Decimal {
+        Decimal(self.0 - rhs.0)
+    }
+}
+
+impl Mul for Decimal {
+    type Output = Decimal;
+
+    fn mul(self, rhs: Decimal) -> Decimal {
+        Decimal(self.0 * rhs.0)
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal(-self.0)
+    }
+}
+
+/// Prints plain digits without trailing zeros, e.g. `2.5` or `-1000`.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.normalized().to_plain_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Decimal;
+
+    #[test]
+    fn decimal_tests() {
+        let data: Vec<(Decimal, &str)> = vec![
+            ("0.1".parse::<Decimal>().unwrap() + "0.2".parse().unwrap(), "0.3"),
+            ("1.50".parse().unwrap(), "1.5"),
+            (".5".parse().unwrap(), "0.5"),
+            ("1200".parse().unwrap(), "1200"),
+            (Decimal::from(10).checked_div(&Decimal::from(3), 10).unwrap(), "3.333333333"),
+            (Decimal::from(2).checked_div(&Decimal::from(-8), 32).unwrap(), "-0.25"),
+            (Decimal::from(2).sqrt(10).unwrap(), "1.414213562"),
+            (Decimal::from(2).powi(-2, 32), "0.25"),
+            ("123.456".parse::<Decimal>().unwrap().round(4), "123.5"),
+            (Decimal::from_f64(0.1 + 0.2).unwrap(), "0.3"),
+            (Decimal::from_f64(-0.49999999999999994).unwrap(), "-0.5"),
+        ];
+
+        for (value, expected_output) in data {
+            assert_eq!(value.to_string(), expected_output);
+        }
+        assert_eq!(Decimal::from(1).checked_div(&Decimal::zero(), 32), None);
+        assert_eq!(Decimal::from(-4).sqrt(32), None);
+        assert_eq!(Decimal::from_f64(f64::INFINITY), None);
+    }
+}
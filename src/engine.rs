@@ -0,0 +1,2391 @@
+use std::collections::BTreeMap;
+
+use num_rational::BigRational;
+use regex::Regex;
+
+use crate::ast::{is_reserved_name, AngleMode, BinaryOperator, Context, Expr, Function, Statement, UserFunction, ANSWER};
+use crate::decimal::Decimal;
+use crate::history::History;
+use crate::keypad::Layout;
+use crate::session::Session;
+use crate::lexer::{tokenize_with, Token, TokenKind, BITWISE_OPERATORS, FRACTION_BAR};
+use crate::number::{format_radixes, format_rational, FractionForm, NumberMode, Word, RADIX_PREFIXES};
+use crate::operations::{evaluate_in_mode, evaluate_in_word, parse_in_mode, parse_statement_in_context, try_parse_expression_with, ParseError};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MessageEmit {
+    Number(u32),
+    Operator(char),
+    Equal,
+    Clear,
+    Delete,
+    Dot,
+    Parentheses(char),
+    Negate,
+    Root,
+    /// `~`, the bitwise complement of the next operand.
+    Not,
+    Function(&'static str),
+    Constant(&'static str),
+    /// Inserts `ans`, the previous result.
+    Answer,
+    /// `%` after an operand, see `Expr::Percent`.
+    Percent,
+    /// Starts a `0x`, `0o` or `0b` literal in programmer mode.
+    RadixPrefix(&'static str),
+    CycleAngleMode,
+    SetNumberMode(NumberMode),
+    /// Goes through the 8, 16, 32 and 64-bit words of programmer mode.
+    CycleWordSize,
+    ToggleSigned,
+    /// Writes a rational result as a fraction, a mixed number or a decimal.
+    CycleFractionForm,
+    /// Puts back the expression of a history entry, by index.
+    RecallExpression(usize),
+    /// Puts back the result of a history entry, by index.
+    RecallResult(usize),
+    ClearHistory,
+    MemoryClear,
+    MemoryRecall,
+    MemoryAdd,
+    MemorySubtract,
+    MemoryStore,
+    /// Stores the value on display in one of the `STORE_NAMES` slots.
+    StoreTo(char),
+    RecallFrom(char),
+    /// Inserts the name of a defined variable.
+    RecallVariable(String),
+    /// Replaces every user function with the definitions of the text, one
+    /// per line, as written in the functions dialog.
+    DefineFunctions(String),
+    /// Handled by the view, which opens the functions dialog.
+    EditFunctions,
+    /// Brings back the display and history line as they were before the last change.
+    Undo,
+    Redo,
+    /// Moves the caret, where the next keys are inserted or deleted.
+    CaretLeft,
+    CaretRight,
+    CaretHome,
+    CaretEnd,
+    /// Inserts text from the clipboard, if it is a valid expression.
+    Paste(String),
+    /// Handled by the view, which copies `copy_text` to the clipboard.
+    Copy,
+    /// Handled by the view, the engine ignores it.
+    SwitchLayout(Layout),
+    /// Stack keys of RPN mode, see `RpnEngine`. The engine ignores them.
+    Swap,
+    RollDown,
+    Drop,
+    /// Handled by the view, which hands the keys to `RpnEngine` instead.
+    ToggleRpn,
+}
+
+impl MessageEmit {
+    /// Settings shared by the algebraic and RPN engines.
+    pub fn is_setting(&self) -> bool {
+        matches!(
+            self,
+            MessageEmit::CycleAngleMode
+                | MessageEmit::SetNumberMode(_)
+                | MessageEmit::CycleWordSize
+                | MessageEmit::ToggleSigned
+        )
+    }
+}
+
+/// Names of the store slots, on top of the memory register.
+pub const STORE_NAMES: [char; 6] = ['A', 'B', 'C', 'D', 'E', 'F'];
+
+/// Oldest changes can no longer be undone past this count.
+pub const MAX_UNDO: usize = 100;
+
+/// What `Undo` and `Redo` bring back.
+#[derive(Debug, PartialEq, Clone)]
+struct Snapshot {
+    result_output: String,
+    memory_output: String,
+    result_shown: bool,
+}
+
+/// Input logic of the calculator, free of any GUI widget.
+/// It consumes `MessageEmit` values and keeps the strings the view displays.
+pub struct CalculatorEngine {
+    result_output: String,
+    memory_output: String,
+    history: History,
+    /// Value of the MC/MR/M+/M-/MS register, as written on the display.
+    memory_register: String,
+    stores: BTreeMap<char, String>,
+    context: Context,
+    number_mode: NumberMode,
+    fraction_form: FractionForm,
+    /// Value on display after `=` in rational mode, to write it in another form.
+    last_result: Option<BigRational>,
+    /// Whether the display holds the result of `=`, which the next operand
+    /// replaces and the next operator continues from.
+    result_shown: bool,
+    undo_stack: Vec<Snapshot>,
+    redo_stack: Vec<Snapshot>,
+    /// Bytes of the expression after the caret, so that it stays in place
+    /// while the display is formatted again.
+    caret_offset: usize
+}
+
+impl Default for CalculatorEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CalculatorEngine {
+    pub fn new() -> CalculatorEngine {
+        CalculatorEngine {
+            result_output: String::from("0 "),
+            memory_output: String::new(),
+            history: History::new(),
+            memory_register: String::from("0"),
+            stores: BTreeMap::new(),
+            context: Context::default(),
+            number_mode: NumberMode::default(),
+            fraction_form: FractionForm::default(),
+            last_result: None,
+            result_shown: false,
+            undo_stack: vec![],
+            redo_stack: vec![],
+            caret_offset: 0
+        }
+    }
+
+    /// Formatted expression (or result) shown on the main display.
+    pub fn display(&self) -> &str {
+        &self.result_output
+    }
+
+    /// Last evaluated expression, or the last error, shown above the display.
+    pub fn history(&self) -> &str {
+        &self.memory_output
+    }
+
+    /// Every evaluated expression with its result.
+    pub fn calculations(&self) -> &History {
+        &self.history
+    }
+
+    pub fn session(&self) -> Session {
+        Session {
+            display: self.result_output.clone(),
+            memory: self.memory_output.clone(),
+            history: self.history.clone(),
+            register: self.memory_register.clone(),
+            stores: self.stores.iter().map(|(name, value)| (*name, value.clone())).collect(),
+            angle_mode: self.context.angle_mode,
+            number_mode: self.number_mode,
+            word: self.context.word,
+            variables: self.context.variables.iter().map(|(name, value)| (name.clone(), value.to_string())).collect(),
+            functions: self.context.functions.iter().map(|(name, function)| function.definition(name)).collect(),
+        }
+    }
+
+    /// Puts back a saved session, the display being formatted again.
+    /// Variables and functions that no longer parse are left out, each with
+    /// a message among those returned.
+    pub fn restore(&mut self, session: Session) -> Vec<String> {
+        let mut dropped: Vec<String> = vec![];
+        let display = session.display.replace(' ', "");
+
+        // The mode decides how the display and `ans` are read
+        self.number_mode = session.number_mode;
+        self.result_output = self.format_result_output(if display.is_empty() { "0".to_string() } else { display });
+        self.memory_output = session.memory;
+        self.history = session.history;
+        // `ans` is the last result, as shown in the history
+        if let Some(answer) = self.history.entries().last().and_then(|entry| parse_in_mode(&entry.result.replace(' ', ""), self.number_mode).ok()) {
+            self.context.answer = answer;
+        }
+        self.memory_register = session.register;
+        self.stores = session.stores.into_iter().collect();
+        self.context.angle_mode = session.angle_mode;
+        self.context.word = session.word;
+        self.context.variables.clear();
+        for (name, value) in session.variables {
+            match parse_in_mode(&value, self.number_mode) {
+                Ok(value) => {
+                    self.context.variables.insert(name, value);
+                },
+                Err(err) => dropped.push(format!("Cannot restore {name}={value}: {err}")),
+            }
+        }
+        self.context.functions.clear();
+        for definition in session.functions {
+            match parse_statement_in_context(&definition, self.number_mode, &self.context) {
+                Ok(Statement::Definition { name, function }) => {
+                    self.context.functions.insert(name, function);
+                },
+                Ok(_) => dropped.push(format!("Cannot restore {definition}: not a function definition")),
+                Err(err) => dropped.push(format!("Cannot restore {definition}: {err}")),
+            }
+        }
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.caret_offset = 0;
+
+        dropped
+    }
+
+    /// Byte index of the caret in `display()`.
+    pub fn caret(&self) -> usize {
+        let mut remaining = self.caret_index(&self.get_trimmed_output());
+
+        for (index, c) in self.result_output.char_indices() {
+            if remaining == 0 {
+                return index;
+            }
+            if c != ' ' {
+                remaining -= c.len_utf8();
+            }
+        }
+
+        self.result_output.len()
+    }
+
+    /// The display without the spaces grouping digits, and with fractions
+    /// written `7/2` or `3 1/2` as spreadsheets read them.
+    pub fn copy_text(&self) -> String {
+        let mixed_number = Regex::new(&format!(r"(?<whole>\d+){FRACTION_BAR}(?<numerator>\d+){FRACTION_BAR}")).unwrap();
+        let output = self.get_trimmed_output();
+
+        mixed_number
+            .replace_all(&output, "$whole $numerator/")
+            .replace(FRACTION_BAR, "/")
+    }
+
+    pub fn angle_mode(&self) -> AngleMode {
+        self.context.angle_mode
+    }
+
+    pub fn number_mode(&self) -> NumberMode {
+        self.number_mode
+    }
+
+    pub fn word(&self) -> Word {
+        self.context.word
+    }
+
+    pub fn fraction_form(&self) -> FractionForm {
+        self.fraction_form
+    }
+
+    pub fn memory_register(&self) -> &str {
+        &self.memory_register
+    }
+
+    pub fn store(&self, name: char) -> Option<&str> {
+        self.stores.get(&name).map(String::as_str)
+    }
+
+    /// Definitions of the user functions as displayed, one per line.
+    pub fn function_definitions(&self) -> String {
+        self.context.functions
+            .iter()
+            .map(|(name, function)| self.format_result_output(function.definition(name)).trim_end().to_string())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Every defined variable by name, with its value as displayed.
+    pub fn variables(&self) -> Vec<(&str, String)> {
+        self.context.variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), self.format_result_output(value.to_string()).trim_end().to_string()))
+            .collect()
+    }
+
+    /// Line shown above the display: the angle mode, or the word in
+    /// programmer mode, `M` while the memory register is not zero, then the
+    /// history.
+    pub fn memory_line(&self) -> String {
+        let mode = match self.number_mode {
+            NumberMode::Programmer => self.context.word.name(),
+            _ => self.context.angle_mode.to_string(),
+        };
+
+        if self.memory_register == "0" {
+            format!("{mode}   {}", self.memory_output)
+        } else {
+            format!("{mode}   M   {}", self.memory_output)
+        }
+    }
+
+    /// Evaluates `expression` as if it were pasted on a cleared display
+    /// before `=`, for front-ends without keypad. Returns the result as
+    /// displayed, the history line after a function definition, or the
+    /// error of the history line.
+    pub fn evaluate_typed(&mut self, expression: &str) -> Result<String, String> {
+        let expression = self.normalize_pasted_text(expression);
+        let statement = parse_statement_in_context(&expression, self.number_mode, &self.context).map_err(|err| err.to_string())?;
+
+        self.handle_message(MessageEmit::Clear);
+        self.handle_message(MessageEmit::Paste(expression));
+        self.handle_message(MessageEmit::Equal);
+
+        if let Statement::Definition { .. } = statement {
+            Ok(self.memory_output.trim().to_string())
+        } else if self.result_shown {
+            Ok(self.result_output.trim().to_string())
+        } else {
+            Err(self.memory_output.trim().to_string())
+        }
+    }
+
+    pub fn handle_message(&mut self, msg: MessageEmit) {
+        let before = self.snapshot();
+        let mut output = self.get_trimmed_output();
+
+        if msg != MessageEmit::CycleFractionForm {
+            self.last_result = None;
+        }
+        if self.result_shown && self.starts_new_expression(&msg) {
+            output = "0".to_string();
+            self.caret_offset = 0;
+        }
+        if !self.keeps_result_shown(&msg) {
+            self.result_shown = false;
+        }
+
+        // Keys act on the expression up to the caret, the rest is put back after
+        let raw_output = output.clone();
+        let tail = if self.edits_at_caret(&msg) {
+            output.split_off(self.caret_index(&output))
+        } else {
+            String::new()
+        };
+
+        if output.is_empty() {
+            output = "0".to_string();
+        }
+
+        match msg {
+            MessageEmit::Number(num) => output = self.handle_message_number(output, num),
+            MessageEmit::Clear => output = self.handle_message_clear(),
+            MessageEmit::Delete => output = self.handle_message_delete(output),
+            MessageEmit::Dot => output = self.handle_message_dot(output, &tail),
+            MessageEmit::Operator(op) => output = self.handle_message_operator(output, op),
+            MessageEmit::Parentheses(par) => output = self.handle_message_parentheses(output, par),
+            MessageEmit::Negate => output = self.handle_message_negate(output),
+            MessageEmit::Root => output = self.handle_message_root(output),
+            MessageEmit::Not => output = self.push_operand(output, "~"),
+            MessageEmit::Function(name) => output = self.handle_message_function(output, name),
+            MessageEmit::Constant(name) => output = self.handle_message_constant(output, name),
+            MessageEmit::Answer => output = self.push_operand(output, ANSWER),
+            MessageEmit::Percent => output = self.handle_message_percent(output),
+            MessageEmit::RadixPrefix(prefix) => output = self.handle_message_radix_prefix(output, prefix),
+            MessageEmit::CycleAngleMode => self.context.angle_mode = self.context.angle_mode.next(),
+            MessageEmit::SetNumberMode(mode) => self.number_mode = mode,
+            MessageEmit::CycleWordSize => self.context.word = self.context.word.next_size(),
+            MessageEmit::ToggleSigned => self.context.word.signed = !self.context.word.signed,
+            MessageEmit::CycleFractionForm => output = self.handle_message_fraction_form(output),
+            MessageEmit::RecallExpression(index) => output = self.handle_message_recall(output, index, false),
+            MessageEmit::RecallResult(index) => output = self.handle_message_recall(output, index, true),
+            MessageEmit::ClearHistory => self.history.clear(),
+            MessageEmit::MemoryClear => self.memory_register = String::from("0"),
+            MessageEmit::MemoryRecall => output = self.push_operand(output, &self.memory_register),
+            MessageEmit::MemoryAdd => self.handle_message_memory(&output, Some('+')),
+            MessageEmit::MemorySubtract => self.handle_message_memory(&output, Some('-')),
+            MessageEmit::MemoryStore => self.handle_message_memory(&output, None),
+            MessageEmit::StoreTo(name) => self.handle_message_store(&output, name),
+            MessageEmit::RecallFrom(name) => {
+                if let Some(value) = self.stores.get(&name) {
+                    output = self.push_operand(output, value);
+                }
+            },
+            MessageEmit::RecallVariable(ref name) => {
+                if self.context.variables.contains_key(name) {
+                    output = self.push_operand(output, name);
+                }
+            },
+            MessageEmit::DefineFunctions(ref text) => self.handle_message_define_functions(text),
+            MessageEmit::EditFunctions => (),
+            MessageEmit::Undo => output = self.handle_message_undo(output, before.clone()),
+            MessageEmit::Redo => output = self.handle_message_redo(output, before.clone()),
+            MessageEmit::CaretLeft => self.handle_message_caret_left(&output),
+            MessageEmit::CaretRight => self.handle_message_caret_right(&output),
+            MessageEmit::CaretHome if output != "0" => self.caret_offset = output.len(),
+            MessageEmit::CaretHome => (),
+            MessageEmit::CaretEnd => self.caret_offset = 0,
+            MessageEmit::Paste(ref text) => output = self.handle_message_paste(output, text),
+            MessageEmit::Copy => (),
+            MessageEmit::SwitchLayout(_) => (),
+            MessageEmit::Swap | MessageEmit::RollDown | MessageEmit::Drop | MessageEmit::ToggleRpn => (),
+            MessageEmit::Equal => {
+                self.caret_offset = 0;
+                output = self.handle_message_equal(output);
+            },
+        }
+
+        if !tail.is_empty() {
+            output = self.join_at_caret(output, &tail);
+        } else if output != raw_output {
+            self.caret_offset = 0;
+        }
+        self.result_output = self.format_result_output(output);
+
+        if msg != MessageEmit::Undo && msg != MessageEmit::Redo && self.snapshot() != before {
+            if self.undo_stack.len() == MAX_UNDO {
+                self.undo_stack.remove(0);
+            }
+            self.undo_stack.push(before);
+            self.redo_stack.clear();
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            result_output: self.result_output.clone(),
+            memory_output: self.memory_output.clone(),
+            result_shown: self.result_shown,
+        }
+    }
+
+    /// Keys that edit the expression where the caret is, rather than the whole of it.
+    fn edits_at_caret(&self, msg: &MessageEmit) -> bool {
+        matches!(
+            msg,
+            MessageEmit::Number(_)
+                | MessageEmit::Delete
+                | MessageEmit::Dot
+                | MessageEmit::Operator(_)
+                | MessageEmit::Parentheses(_)
+                | MessageEmit::Negate
+                | MessageEmit::Root
+                | MessageEmit::Not
+                | MessageEmit::Function(_)
+                | MessageEmit::Constant(_)
+                | MessageEmit::Answer
+                | MessageEmit::Percent
+                | MessageEmit::RadixPrefix(_)
+                | MessageEmit::MemoryRecall
+                | MessageEmit::RecallFrom(_)
+                | MessageEmit::RecallVariable(_)
+                | MessageEmit::Paste(_)
+        )
+    }
+
+    /// Puts back the part after the caret, multiplying where two operands
+    /// would otherwise touch, as when typing `2` before `π`.
+    fn join_at_caret(&self, mut head: String, tail: &str) -> String {
+        if head == "0" {
+            head.clear();
+        }
+
+        // More digits of a `0x1F` literal
+        if self.radix_literal(&head).is_some() && tail.starts_with(|c: char| c.is_ascii_hexdigit()) {
+            head.push_str(tail);
+            return head;
+        }
+
+        let tail_starts_with_number = tail.starts_with(|c: char| c.is_ascii_digit() || c == '.');
+        let tail_starts_with_name = tail.starts_with('(')
+            || matches!(self.tokens(tail).first(), Some(Token { kind: TokenKind::Identifier(name), .. }) if name != "x");
+        let head_ends_with_value = head.ends_with(')') || self.needs_times_before_number(&head);
+
+        if (self.ends_with_operand(&head) && tail_starts_with_name) || (head_ends_with_value && tail_starts_with_number) {
+            head.push('x');
+        }
+        head.push_str(tail);
+
+        head
+    }
+
+    /// Pasted text is written the way the keypad would: `*` and `×` become
+    /// `x`, `÷` becomes `/`, `<<` and `>>` become `«` and `»`, and digit
+    /// group separators go away. Text that
+    /// does not parse is left out, with the error on the history line.
+    fn handle_message_paste(&mut self, output: String, text: &str) -> String {
+        let expression = self.normalize_pasted_text(text);
+
+        match parse_statement_in_context(&expression, self.number_mode, &self.context) {
+            Ok(_) => self.join_at_caret(output, &expression),
+            Err(err) => {
+                self.memory_output = format!("Cannot paste: {err}  ");
+                output
+            },
+        }
+    }
+
+    /// Commas are kept between the arguments of a user function only.
+    fn normalize_pasted_text(&self, text: &str) -> String {
+        let mut normalized = String::new();
+        // Whether each open parenthese holds the arguments of a user function
+        let mut argument_lists: Vec<bool> = vec![];
+
+        for c in text.replace("<<", "«").replace(">>", "»").chars() {
+            match c {
+                '(' => argument_lists.push(self.ends_with_user_function(&normalized)),
+                ')' => {
+                    argument_lists.pop();
+                },
+                ',' if argument_lists.last() == Some(&true) => (),
+                ',' | '\'' | '_' => continue,
+                c if c.is_whitespace() => continue,
+                _ => (),
+            }
+            normalized.push(match c {
+                '*' | '×' => 'x',
+                '÷' => '/',
+                c => c,
+            });
+        }
+
+        normalized
+    }
+
+    /// Byte index of the caret in `output`, kept inside it and on a char boundary.
+    fn caret_index(&self, output: &str) -> usize {
+        let mut index = output.len().saturating_sub(self.caret_offset);
+
+        while !output.is_char_boundary(index) {
+            index -= 1;
+        }
+
+        index
+    }
+
+    /// The caret steps over a function name and its parenthese, or `ans`, at once.
+    fn handle_message_caret_left(&mut self, output: &str) {
+        let head = &output[..self.caret_index(output)];
+
+        if output == "0" {
+            return;
+        }
+        self.caret_offset = output.len() - head.len();
+        self.caret_offset += match Function::ALL.iter().find(|function| head.ends_with(&format!("{}(", function.name()))) {
+            Some(function) => function.name().len() + 1,
+            None if head.ends_with(ANSWER) => ANSWER.len(),
+            None => head.chars().last().map_or(0, char::len_utf8),
+        };
+    }
+
+    fn handle_message_caret_right(&mut self, output: &str) {
+        let tail = &output[self.caret_index(output)..];
+
+        self.caret_offset = tail.len();
+        self.caret_offset -= match Function::ALL.iter().find(|function| tail.starts_with(&format!("{}(", function.name()))) {
+            Some(function) => function.name().len() + 1,
+            None if tail.starts_with(ANSWER) => ANSWER.len(),
+            None => tail.chars().next().map_or(0, char::len_utf8),
+        };
+    }
+
+    /// Puts `snapshot` back and returns its display, trimmed like `output`.
+    fn restore_snapshot(&mut self, snapshot: Snapshot) -> String {
+        self.memory_output = snapshot.memory_output;
+        self.result_shown = snapshot.result_shown;
+
+        snapshot.result_output.replace(' ', "")
+    }
+
+    fn handle_message_undo(&mut self, output: String, current: Snapshot) -> String {
+        match self.undo_stack.pop() {
+            Some(snapshot) => {
+                self.redo_stack.push(current);
+                self.restore_snapshot(snapshot)
+            },
+            None => output,
+        }
+    }
+
+    fn handle_message_redo(&mut self, output: String, current: Snapshot) -> String {
+        match self.redo_stack.pop() {
+            Some(snapshot) => {
+                self.undo_stack.push(current);
+                self.restore_snapshot(snapshot)
+            },
+            None => output,
+        }
+    }
+
+    /// After `name=`, the result is also given to the variable `name`.
+    fn handle_message_equal(&mut self, output: String) -> String {
+        let mut output = self.complete_expression(output);
+
+        if let Ok(Statement::Definition { name, function }) = parse_statement_in_context(&output, self.number_mode, &self.context) {
+            self.memory_output = format!("{} defined  ", self.format_signature(&name, &function));
+            self.context.functions.insert(name, function);
+            return "0".to_string();
+        }
+        let evaluated = self.split_assignment(&output).and_then(|(name, expression)| {
+            Ok((name, self.evaluate(expression)?, self.radixes_line(expression)))
+        });
+
+        match evaluated {
+            Ok((name, (result, value), radixes)) => {
+                if let Ok(answer) = self.answer(&result, value.as_ref()) {
+                    if let Some(name) = name {
+                        self.context.variables.insert(name, answer.clone());
+                    }
+                    self.context.answer = answer;
+                }
+                self.last_result = value;
+                self.result_shown = true;
+                output = self.format_result_output(output);
+                self.history.push(&output, &self.format_result_output(result.clone()));
+                output.push_str("=  ");
+                self.memory_output = output;
+
+                if let Some(radixes) = radixes {
+                    self.memory_output = radixes;
+                }
+
+                result
+            },
+            Err(err) => {
+                self.memory_output = format!("{err}  ");
+
+                if output.is_empty() {
+                    output = "0".to_string();
+                }
+                output
+            }
+        }
+    }
+
+    /// The variable `output` assigns, if any, and the expression giving its value.
+    fn split_assignment<'a>(&self, output: &'a str) -> Result<(Option<String>, &'a str), ParseError> {
+        match parse_statement_in_context(output, self.number_mode, &self.context)? {
+            Statement::Assignment { name, .. } => Ok((Some(name), output.split_once('=').map_or(output, |(_, value)| value))),
+            Statement::Expression(_) | Statement::Definition { .. } => Ok((None, output)),
+        }
+    }
+
+    /// `f(x, y)`, as in the history line.
+    fn format_signature(&self, name: &str, function: &UserFunction) -> String {
+        format!("{name}({})", function.parameters.join(", "))
+    }
+
+    /// In programmer mode, the result in every base of the word, after a
+    /// warning if it did not fit, to show instead of the expression.
+    fn radixes_line(&self, expression: &str) -> Option<String> {
+        if self.number_mode != NumberMode::Programmer {
+            return None;
+        }
+        let (value, overflowed) = evaluate_in_word(expression, &self.context).ok()?;
+        let warning = if overflowed { "Overflow   " } else { "" };
+
+        Some(format!("{warning}{}  ", format_radixes(&value, self.context.word)))
+    }
+
+    /// Drops what cannot end an expression and closes open parentheses.
+    fn complete_expression(&self, mut output: String) -> String {
+        output = self.drop_bare_radix_prefix(output);
+
+        loop {
+            output = output
+                .trim_end_matches(|c: char| {
+                    ['(', '+', '-', '/', '^', '√', '~', '.', ' ', ',', FRACTION_BAR].contains(&c) || BITWISE_OPERATORS.contains(&c)
+                })
+                .to_string();
+
+            if self.ends_with_times(&output) {
+                output.pop();
+                continue;
+            }
+
+            // Drop a function left without argument along with its parenthese,
+            // rather than a variable whose name ends the same
+            let last_token = self.last_token(&output);
+            let is_function_name = |name: &str| matches!(&last_token, Some(TokenKind::Identifier(last_name)) if last_name == name);
+
+            match Function::ALL.iter().find(|function| is_function_name(function.name())) {
+                Some(function) => output.truncate(output.len() - function.name().len()),
+                None => break,
+            }
+        }
+
+        let opened_par_count = output.chars().filter(|c| *c == '(').count();
+        let mut closed_par_count = output.chars().filter(|c| *c == ')').count();
+
+        while closed_par_count < opened_par_count {
+            output.push(')');
+            closed_par_count += 1;
+        }
+
+        output
+    }
+
+    /// Evaluates in the current number mode. Rational results are written in
+    /// the current fraction form and also returned as a value.
+    fn evaluate(&self, expression: &str) -> Result<(String, Option<BigRational>), ParseError> {
+        match self.number_mode {
+            NumberMode::Rational => try_parse_expression_with::<BigRational>(expression, &self.context).map(|result| {
+                let formatted = format_rational(&result.value, self.fraction_form, self.context.precision);
+
+                (formatted, Some(result.value))
+            }),
+            mode => evaluate_in_mode(expression, mode, &self.context).map(|result| (result, None)),
+        }
+    }
+
+    /// What `ans` stands for after `=`: the exact fraction in rational mode,
+    /// the result as written otherwise.
+    fn answer(&self, result: &str, value: Option<&BigRational>) -> Result<Expr, ParseError> {
+        match value {
+            Some(value) => Ok(Expr::binary(
+                BinaryOperator::Divide,
+                Expr::Number(Decimal::from(value.numer().clone())),
+                Expr::Number(Decimal::from(value.denom().clone())),
+            )),
+            None => parse_in_mode(result, self.number_mode),
+        }
+    }
+
+    /// A number, a name or an opening parenthese after `=` starts a new
+    /// expression rather than extending the result.
+    fn starts_new_expression(&self, msg: &MessageEmit) -> bool {
+        matches!(
+            msg,
+            MessageEmit::Number(_)
+                | MessageEmit::Dot
+                | MessageEmit::Parentheses('(')
+                | MessageEmit::Root
+                | MessageEmit::Not
+                | MessageEmit::Function(_)
+                | MessageEmit::Constant(_)
+                | MessageEmit::Answer
+                | MessageEmit::RadixPrefix(_)
+                | MessageEmit::MemoryRecall
+                | MessageEmit::RecallFrom(_)
+                | MessageEmit::RecallVariable(_)
+                | MessageEmit::Paste(_)
+        )
+    }
+
+    /// Messages that leave the display alone, so that the result of `=` is
+    /// still there to continue from.
+    fn keeps_result_shown(&self, msg: &MessageEmit) -> bool {
+        matches!(
+            msg,
+            MessageEmit::CycleAngleMode
+                | MessageEmit::SetNumberMode(_)
+                | MessageEmit::CycleWordSize
+                | MessageEmit::ToggleSigned
+                | MessageEmit::CycleFractionForm
+                | MessageEmit::ClearHistory
+                | MessageEmit::MemoryClear
+                | MessageEmit::MemoryAdd
+                | MessageEmit::MemorySubtract
+                | MessageEmit::MemoryStore
+                | MessageEmit::StoreTo(_)
+                | MessageEmit::CaretEnd
+                | MessageEmit::Copy
+                | MessageEmit::Undo
+                | MessageEmit::Redo
+                | MessageEmit::SwitchLayout(_)
+                | MessageEmit::DefineFunctions(_)
+                | MessageEmit::EditFunctions
+                | MessageEmit::Swap
+                | MessageEmit::RollDown
+                | MessageEmit::Drop
+                | MessageEmit::ToggleRpn
+        )
+    }
+
+    /// MS, M+ and M-: `operator` combines the register with the value of the
+    /// expression on display, which stays as it is.
+    fn handle_message_memory(&mut self, output: &str, operator: Option<char>) {
+        let value = self.complete_expression(output.to_string());
+        let expression = match operator {
+            Some(operator) => format!("({}){operator}({value})", self.memory_register),
+            None => value,
+        };
+
+        match self.evaluate(&expression) {
+            Ok((result, _)) => self.memory_register = result,
+            Err(err) => self.memory_output = format!("{err}  "),
+        }
+    }
+
+    fn handle_message_store(&mut self, output: &str, name: char) {
+        let value = self.complete_expression(output.to_string());
+
+        match self.evaluate(&value) {
+            Ok((result, _)) => {
+                self.stores.insert(name, result);
+            },
+            Err(err) => self.memory_output = format!("{err}  "),
+        }
+    }
+
+    /// Nothing changes when a line is not a definition, the error going to
+    /// the history line.
+    fn handle_message_define_functions(&mut self, text: &str) {
+        let mut functions = BTreeMap::new();
+
+        for (index, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            match parse_statement_in_context(&self.normalize_pasted_text(line), self.number_mode, &self.context) {
+                Ok(Statement::Definition { name, function }) => {
+                    functions.insert(name, function);
+                },
+                Ok(_) => {
+                    self.memory_output = format!("Line {}: not a function definition  ", index + 1);
+                    return;
+                },
+                Err(err) => {
+                    self.memory_output = format!("Line {}: {err}  ", index + 1);
+                    return;
+                },
+            }
+        }
+        self.context.functions = functions;
+    }
+
+    fn handle_message_fraction_form(&mut self, output: String) -> String {
+        self.fraction_form = self.fraction_form.next();
+
+        match &self.last_result {
+            Some(value) => format_rational(value, self.fraction_form, self.context.precision),
+            None => output,
+        }
+    }
+
+    fn handle_message_recall(&self, output: String, index: usize, result: bool) -> String {
+        match self.history.get(index) {
+            Some(entry) if result => entry.result.replace(' ', ""),
+            Some(entry) => entry.expression.replace(' ', ""),
+            None => output,
+        }
+    }
+
+    fn handle_message_parentheses(&self, mut output: String, par: char) -> String {
+        if par == ')' {
+            let opened_par_count = output.chars().filter(|c| *c == '(').count();
+            let closed_par_count = output.chars().filter(|c| *c == ')').count();
+
+            if opened_par_count > closed_par_count {
+                while self.ends_with_operator(&output) {
+                    output.pop();
+                }
+                output.push(par);
+            }
+        } else if output == "0" {
+            output = "(".to_string();
+        } else {
+            if self.needs_times_before_number(&output) {
+                output.push('x');
+            }
+            output.push(par);
+        }
+
+        output
+    }
+
+    /// `tail` is what follows the caret, the rest of the number being there
+    /// when the caret is inside it.
+    fn handle_message_dot(&self, mut output: String, tail: &str) -> String {
+        // Radix literals are integers
+        if self.radix_literal(&output).is_some() {
+            return output;
+        }
+        if tail.chars().take_while(|c| c.is_ascii_digit() || *c == '.').any(|c| c == '.') {
+            return output;
+        }
+
+        if !output.is_empty() {
+            if self.ends_with_operator(&output) {
+                output.push('.');
+            } else if self.needs_times_before_number(&output) {
+                output.push_str("x.");
+            } else {
+                let last_elem: String = output
+                    .rsplit(|c: char| c == '√' || c == '~' || self.is_an_operator(c))
+                    .next()
+                    .unwrap()
+                    .to_string();
+
+                if last_elem
+                    .chars()
+                    .filter(|c| *c == '.')
+                    .count() == 0
+                {
+                    output.insert(output.len(), '.');
+                }
+            }
+        }
+
+        output
+    }
+
+    fn handle_message_operator(&self, mut output: String, op: char) -> String {
+        output = self.drop_bare_radix_prefix(output);
+
+        if !output.is_empty() {
+            // A minus after '(', '√', '~' or an operator other than '+' and '-' is the sign of the next operand
+            if op == '-' && (output.ends_with(['(', '√', '~']) || (self.ends_with_operator(&output) && !output.ends_with(['+', '-']))) {
+                output.push(op);
+                return output;
+            }
+            if op == '-' && self.ends_with_sign(&output) {
+                return output;
+            }
+
+            while self.ends_with_operator(&output) || output.ends_with(['√', '~']) {
+                output.pop();
+            }
+            if output.is_empty() || output.ends_with('(') {
+                output.push('0');
+            }
+            output = format!("{output}{op}");
+        }
+
+        output
+    }
+
+    fn handle_message_negate(&self, mut output: String) -> String {
+        if output == "0" {
+            return "-".to_string();
+        }
+
+        // A percentage is signed as a whole, as in `200+-10%`
+        let operand = output.trim_end_matches('%');
+
+        let mut operand_start = if operand.ends_with(')') {
+            self.find_opening_parenthese(operand)
+        } else if let Some((literal_start, _)) = self.radix_literal(operand) {
+            literal_start
+        } else {
+            operand
+                .char_indices()
+                .rfind(|(_, c)| !c.is_ascii_digit() && *c != '.' && *c != FRACTION_BAR)
+                .map_or(0, |(index, c)| index + c.len_utf8())
+        };
+
+        // A constant or `ans` is an operand on its own
+        if operand_start == operand.len() && self.ends_with_constant(operand) {
+            operand_start = self.tokens(operand).last().map_or(0, |token| token.offset);
+        }
+
+        if let Some(function) = Function::ALL.iter().find(|function| output[..operand_start].ends_with(function.name())) {
+            operand_start -= function.name().len();
+        }
+
+        // The sign goes before a square root, but not between the two operands of `n√x`
+        while output[..operand_start].ends_with('√')
+            && !output[..operand_start - '√'.len_utf8()].ends_with(|c: char| c.is_ascii_digit() || c == '.' || c == ')')
+        {
+            operand_start -= '√'.len_utf8();
+        }
+
+        if self.ends_with_sign(&output[..operand_start]) {
+            output.remove(operand_start - 1);
+        } else {
+            output.insert(operand_start, '-');
+        }
+
+        if output.is_empty() {
+            output = "0".to_string();
+        }
+        output
+    }
+
+    fn handle_message_percent(&self, mut output: String) -> String {
+        if self.ends_with_operand(&output) && !output.ends_with('.') {
+            output.push('%');
+        }
+
+        output
+    }
+
+    /// A prefix right after another one replaces it.
+    fn handle_message_radix_prefix(&self, output: String, prefix: &str) -> String {
+        if !self.number_mode.reads_radix_prefixes() {
+            return output;
+        }
+        let output = self.drop_bare_radix_prefix(output);
+
+        self.push_operand(output, prefix)
+    }
+
+    fn handle_message_root(&self, mut output: String) -> String {
+        if output == "0" {
+            output = "√".to_string();
+        } else {
+            output.push('√');
+        }
+
+        output
+    }
+
+    fn handle_message_function(&self, mut output: String, name: &str) -> String {
+        if output == "0" {
+            output.clear();
+        } else if self.ends_with_operand(&output) {
+            output.push('x');
+        }
+        output.push_str(name);
+        output.push('(');
+
+        output
+    }
+
+    fn handle_message_constant(&self, output: String, name: &str) -> String {
+        self.push_operand(output, name)
+    }
+
+    /// Appends a constant or a recalled value, multiplying what was before it.
+    fn push_operand(&self, mut output: String, operand: &str) -> String {
+        if output == "0" {
+            output.clear();
+        } else if self.ends_with_operand(&output) {
+            output.push('x');
+        }
+        output.push_str(operand);
+
+        output
+    }
+
+    /// Digits from 10 to 15 are the hexadecimal `A` to `F`, which start a
+    /// `0x` literal unless they extend one.
+    fn handle_message_number(&self, mut output: String, num: u32) -> String {
+        let output_len = output.len();
+        let Some(digit) = char::from_digit(num, 16).map(|c| c.to_ascii_uppercase()) else {
+            return output;
+        };
+
+        if let Some((_, radix)) = self.radix_literal(&output) {
+            if num < radix && output_len <= 100 {
+                output.push(digit);
+            }
+            return output;
+        }
+        if num >= 10 {
+            if self.number_mode.reads_radix_prefixes() {
+                output = self.push_operand(output, &format!("0x{digit}"));
+            }
+            return output;
+        }
+
+        if output == "0" {
+            output = format!("{num}");
+        } else if self.needs_times_before_number(&output) {
+            output.push('x');
+            output.push(digit);
+        } else if output_len <= 100 { // A CHANGER
+            output.insert(output_len, digit);
+        };
+
+        output
+    }
+
+    fn handle_message_clear(&mut self) -> String {
+        self.memory_output.clear();
+        "0".to_string()
+    }
+
+    fn handle_message_delete(&self, mut output: String) -> String {
+        if output.ends_with(ANSWER) {
+            output.truncate(output.len() - ANSWER.len());
+        } else if output != "0" && output.pop() == Some('(') {
+            // A function name goes away with its parenthese
+            if let Some(function) = Function::ALL.iter().find(|function| output.ends_with(function.name())) {
+                output.truncate(output.len() - function.name().len());
+            }
+        }
+
+        if output.is_empty() {
+            output = "0".to_string();
+        }
+
+        output
+    }
+
+    /// Function names are always followed by their parenthese, so a trailing
+    /// name is a constant or a variable, unless it is the `x` of `f(x)=2x`,
+    /// which a number typed next multiplies instead.
+    fn ends_with_constant(&self, output: &str) -> bool {
+        let tokens = self.tokens(output);
+
+        matches!(tokens.last(), Some(Token { kind: TokenKind::Identifier(_), .. }))
+            && !tokens.iter().any(|token| token.kind == TokenKind::Times && token.offset + 1 == output.len())
+    }
+
+    /// Whether a number typed next would be a new operand rather than more
+    /// digits, after a constant, a percentage or a hexadecimal letter.
+    fn needs_times_before_number(&self, output: &str) -> bool {
+        self.ends_with_constant(output)
+            || output.ends_with('%')
+            || (self.radix_literal(output).is_some() && output.ends_with(|c: char| c.is_ascii_uppercase()))
+    }
+
+    /// Start and radix of the `0x1F`, `0o17` or `0b1010` literal ending
+    /// `output`, digits or not, in programmer mode.
+    fn radix_literal(&self, output: &str) -> Option<(usize, u32)> {
+        let head = output.trim_end_matches(|c: char| c.is_ascii_digit() || ('A'..='F').contains(&c));
+
+        RADIX_PREFIXES
+            .iter()
+            .find(|(prefix, _)| head.ends_with(prefix) && self.starts_radix_literal(output, head.len() - prefix.len()))
+            .map(|(prefix, radix)| (head.len() - prefix.len(), *radix))
+    }
+
+    /// Whether the `0` at `index` starts a radix prefix, rather than ending
+    /// a number as in `10x5`. False when `index` falls inside a character
+    /// such as `π`.
+    fn starts_radix_literal(&self, output: &str, index: usize) -> bool {
+        let Some(tail) = output.get(index..) else {
+            return false;
+        };
+
+        self.number_mode.reads_radix_prefixes()
+            && RADIX_PREFIXES.iter().any(|(prefix, _)| tail.starts_with(prefix))
+            && !output[..index].ends_with(|c: char| c.is_ascii_digit() || c == '.' || ('A'..='F').contains(&c))
+    }
+
+    /// Drops a radix prefix no digit follows yet, leaving its `0`.
+    fn drop_bare_radix_prefix(&self, mut output: String) -> String {
+        if self.radix_literal(&output).is_some_and(|(start, _)| output.len() == start + 2) {
+            output.pop();
+        }
+
+        output
+    }
+
+    /// Whether `output` ends with the multiplication sign, rather than with
+    /// an `x` ending a name, as in `tax` or `f(x)=2+x`.
+    fn ends_with_times(&self, output: &str) -> bool {
+        self.last_token(output) == Some(TokenKind::Times)
+    }
+
+    /// Whether `output` ends with an operator an operand should follow.
+    fn ends_with_operator(&self, output: &str) -> bool {
+        output.ends_with(|c: char| c != 'x' && self.is_an_operator(c)) || self.ends_with_times(output)
+    }
+
+    /// Offsets of the `x`s of `output` that only stand for the
+    /// multiplication sign, the `x` of `f(x)=2x` being a name too. Past what
+    /// the lexer can read, as after a lone `.`, an `x` after an operand is
+    /// still one.
+    fn multiplication_signs(&self, output: &str) -> Vec<usize> {
+        let (tokens, read) = self.read_tokens(output);
+        let mut signs: Vec<usize> = tokens
+            .iter()
+            .filter(|token| token.kind == TokenKind::Times)
+            .map(|token| token.offset)
+            .filter(|offset| !tokens.iter().any(|token| token.offset == *offset && token.kind != TokenKind::Times))
+            .collect();
+
+        signs.extend(output.char_indices().filter_map(|(index, c)| {
+            let after_operand = output[..index].ends_with(|c: char| c.is_ascii_digit() || ['.', ')', '%', 'π'].contains(&c));
+
+            (index >= read && c == 'x' && after_operand).then_some(index)
+        }));
+
+        signs
+    }
+
+    /// Whether `output` ends with a name that can only be a user function
+    /// when a parenthese follows.
+    fn ends_with_user_function(&self, output: &str) -> bool {
+        matches!(self.last_token(output), Some(TokenKind::Identifier(name)) if !is_reserved_name(&name))
+    }
+
+    /// The last token of `output`, as the lexer reads it.
+    fn last_token(&self, output: &str) -> Option<TokenKind> {
+        self.tokens(output).pop().map(|token| token.kind)
+    }
+
+    /// The tokens of `output`, as the lexer reads it knowing the variables
+    /// and functions, up to the first error if any.
+    fn tokens(&self, output: &str) -> Vec<Token> {
+        self.read_tokens(output).0
+    }
+
+    /// Like `tokens`, also giving the length of `output` they were read
+    /// from, which is shorter after an error.
+    fn read_tokens(&self, output: &str) -> (Vec<Token>, usize) {
+        match tokenize_with(output, self.number_mode.reads_radix_prefixes(), &|name| self.context.defines(name)) {
+            Ok(tokens) => (tokens, output.len()),
+            Err(err) => match output.get(..err.offset()).filter(|head| head.len() < output.len()) {
+                Some(head) => (self.tokens(head), head.len()),
+                None => (vec![], 0),
+            },
+        }
+    }
+
+    /// Whether `output` ends with a number, a constant, a percentage or a closing parenthese.
+    fn ends_with_operand(&self, output: &str) -> bool {
+        output.ends_with(|c: char| c.is_ascii_digit() || c == '.' || c == ')') || self.needs_times_before_number(output)
+    }
+
+    /// Whether `output` ends with a minus that is a sign rather than a subtraction.
+    fn ends_with_sign(&self, output: &str) -> bool {
+        output
+            .strip_suffix('-')
+            .is_some_and(|head| head.is_empty() || head.ends_with(['(', '√', '~']) || self.ends_with_operator(head))
+    }
+
+    fn find_opening_parenthese(&self, output: &str) -> usize {
+        let mut depth: usize = 0;
+
+        for (index, c) in output.char_indices().rev() {
+            match c {
+                ')' => depth += 1,
+                '(' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return index;
+                    }
+                },
+                _ => (),
+            }
+        }
+        0
+    }
+
+    fn get_trimmed_output(&self) -> String {
+        self.result_output.replace(' ', "")
+    }
+
+    /// Splits after every operator, parenthese or comma, like
+    /// `split_inclusive`. An `x` is cut after only when its offset is one of
+    /// `signs`, rather than in a name such as `exp`, and never in a bare
+    /// `0x` prefix.
+    fn split_segments<'a>(&self, raw_output: &'a str, signs: &[usize]) -> Vec<&'a str> {
+        let mut segments: Vec<&str> = vec![];
+        let mut segment_start: usize = 0;
+
+        for (index, c) in raw_output.char_indices() {
+            if c == 'x' && (!signs.contains(&index) || (index > 0 && self.starts_radix_literal(raw_output, index - 1))) {
+                continue;
+            } else if ['√', '~', '(', ')', '%', ','].contains(&c) || self.is_an_operator(c) {
+                segments.push(&raw_output[segment_start..index + c.len_utf8()]);
+                segment_start = index + c.len_utf8();
+            }
+        }
+        if segment_start < raw_output.len() {
+            segments.push(&raw_output[segment_start..]);
+        }
+
+        segments
+    }
+
+    fn format_result_output(&self, raw_output: String) -> String {
+        let signs = self.multiplication_signs(&raw_output);
+
+        // What an assignment or a definition defines is set apart from its value
+        if let Some((head, value)) = raw_output.split_once('=') {
+            let value_signs: Vec<usize> = signs.iter().filter_map(|sign| sign.checked_sub(head.len() + 1)).collect();
+            let head = self.format_expression(head, &signs);
+
+            return format!("{} = {}", head.trim_end(), self.format_expression(value, &value_signs).trim_start());
+        }
+
+        self.format_expression(&raw_output, &signs)
+    }
+
+    /// Formats an expression with no `=`, the `x`s at `signs` being
+    /// multiplication signs.
+    fn format_expression(&self, raw_output: &str, signs: &[usize]) -> String {
+        let mut formated_output = String::from("");
+        let segments: Vec<&str> = self.split_segments(raw_output, signs);
+
+        let mut previous_char: Option<char> = None;
+        let mut segment_end: usize = 0;
+
+        for elem in segments {
+            segment_end += elem.len();
+
+            // A bare `0x` is kept whole rather than read as zero times, and
+            // the parameter `x` is not an operator
+            let trimmed_number = match self.radix_literal(elem) {
+                Some(_) => elem,
+                None if elem.ends_with('x') && !signs.contains(&(segment_end - 1)) => elem,
+                // A segment ends with one delimiter at most, maybe after the parameter `x`
+                None => elem
+                    .strip_suffix(|c: char| ['√', '~', '(', ')', '%', ','].contains(&c) || self.is_an_operator(c))
+                    .unwrap_or(elem),
+            };
+            let is_sign = elem == "-" && previous_char.is_none_or(|c| c == '(' || c == ',' || c == '√' || c == '~' || self.is_an_operator(c));
+
+            let is_function_name = trimmed_number.starts_with(char::is_alphabetic);
+
+            // Add spaces to integer part if needed every 3 decimals
+            if is_function_name {
+                formated_output.push_str(trimmed_number);
+            } else {
+                formated_output.push_str(&self.format_number(trimmed_number));
+            }
+            // Add spaces around operators, but keep signs, roots and calls stuck to their operand
+            if is_sign {
+                formated_output.push('-');
+            } else if elem.ends_with('√') {
+                formated_output.push('√');
+            } else if elem.ends_with('~') {
+                formated_output.push('~');
+            } else if elem.ends_with('%') {
+                formated_output.push('%');
+            } else if is_function_name && elem.ends_with('(') {
+                formated_output.push('(');
+            } else if trimmed_number != elem {
+                formated_output.push_str(&self.add_spaces_around_operators(elem));
+            }
+            previous_char = elem.chars().last();
+        }
+
+        self.replace_patterns_and_add_last_space(formated_output)
+    }
+
+    fn replace_patterns_and_add_last_space(&self, mut formated_output: String) -> String {
+        if let Some(c) = formated_output.chars().last() {
+            if c != ' ' {
+                formated_output.push(' ');
+            }
+        }
+
+        formated_output = formated_output.replace("  ", " ");
+        formated_output = formated_output.replace(" .", " 0.");
+        formated_output = formated_output.replace("(.", "(0.");
+        formated_output = formated_output.replace("-.", "-0.");
+        formated_output = formated_output.replace(") (", ") x (");
+        formated_output = formated_output.replace("()", "(1)");
+
+        let mut regex = Regex::new(r"(?<n>\d) \(").unwrap();
+        formated_output = regex.replace_all(&formated_output, r"$n x (").to_string();
+
+        regex = Regex::new(r"\) (?<n>\d)").unwrap();
+        formated_output = regex.replace_all(&formated_output, r") x $n").to_string();
+
+        formated_output
+    }
+
+    fn add_spaces_around_operators(&self, segment: &str) -> String {
+        let last_char: char = segment.chars().last().unwrap();
+
+        if self.is_an_operator(last_char) {
+            format!(" {last_char} ")
+        } else if last_char == '(' {
+            format!(" {last_char}")
+        } else if last_char == ')' || last_char == ',' {
+            format!("{last_char} ")
+        } else {
+            "".to_string()
+        }
+    }
+
+    fn format_number(&self, number: &str) -> String {
+        // Radix literals are not grouped
+        if self.radix_literal(number).is_some_and(|(start, _)| start == 0) {
+            return number.to_string();
+        }
+
+        if number.contains(FRACTION_BAR) {
+            return number
+                .split(FRACTION_BAR)
+                .map(|term| self.format_number(term))
+                .collect::<Vec<String>>()
+                .join(&FRACTION_BAR.to_string());
+        }
+
+        match number.split_once('.') {
+            Some((integer_part, decimal_part)) => {
+                let formated_integer_part: String = self.format_integer_part(integer_part);
+
+                format!("{formated_integer_part}.{decimal_part}")
+            },
+            None => self.format_integer_part(number)
+        }
+    }
+
+    /// Spaces every three digits of each run of ASCII digits, from its end,
+    /// leaving anything else as it is.
+    fn format_integer_part(&self, integer_part: &str) -> String {
+        let mut formated_integer = String::new();
+        let mut rest = integer_part;
+
+        while !rest.is_empty() {
+            let digits_end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            let (digits, tail) = rest.split_at(digits_end);
+            let other_end = tail.find(|c: char| c.is_ascii_digit()).unwrap_or(tail.len());
+            let mut grouped = String::from(digits);
+
+            for add_space_index in (1..=digits.len().saturating_sub(3)).rev().step_by(3) {
+                grouped.insert(add_space_index, ' ');
+            }
+            formated_integer.push_str(&grouped);
+            formated_integer.push_str(&tail[..other_end]);
+            rest = &tail[other_end..];
+        }
+
+        formated_integer
+    }
+
+    fn is_an_operator(&self, op: char) -> bool {
+        op == 'x' || op == '+' || op == '-' || op == '/' || op == '^' || BITWISE_OPERATORS.contains(&op)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{CalculatorEngine, MessageEmit, MAX_UNDO};
+    use crate::ast::AngleMode;
+    use crate::number::{FractionForm, NumberMode, Word};
+
+    #[test]
+    fn get_trimmed_output_tests() {
+        let mut engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("1 123 456 + 156 - 15 / .23 x 9 156 + 0 "), String::from("1123456+156-15/.23x9156+0")),
+            (String::from("0 + 5 - 1 123 / "), String::from("0+5-1123/")),
+            (String::from("0 "), String::from("0")),
+        ];
+
+        for (input, expected_output) in data {
+            engine.result_output = input;
+
+            assert_eq!(engine.get_trimmed_output(), expected_output);
+        }
+    }
+
+    #[test]
+    fn handle_message_number_tests() {
+        let engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("0"), 1, String::from("1")),
+            (String::from("1"), 1, String::from("11")),
+            (String::from("15+"), 1, String::from("15+1")),
+            (String::from("15-"), 1, String::from("15-1")),
+            (String::from("15."), 1, String::from("15.1")),
+            (String::from("0"), 0, String::from("0")),
+            (String::from("π"), 2, String::from("πx2")),
+        ];
+
+        for (input, num, expected_output) in data {
+            let output = engine.handle_message_number(input, num);
+
+            assert_eq!(output, expected_output);
+        }
+    }
+
+    #[test]
+    fn handle_message_clear_tests() {
+        let mut engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("0"), String::from("0")),
+            (String::from("1564"), String::from("0")),
+            (String::from("15+"), String::from("0")),
+            (String::from("0.226x"), String::from("0")),
+        ];
+
+        for (input, expected_output) in data {
+            engine.result_output = input;
+
+            assert_eq!(engine.handle_message_clear(), expected_output);
+        }
+    }
+
+    #[test]
+    fn handle_message_delete_tests() {
+        let engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("0"), String::from("0")),
+            (String::from("1564"), String::from("156")),
+            (String::from("15+"), String::from("15")),
+            (String::from("0.226x"), String::from("0.226")),
+            (String::from("0.226"), String::from("0.22")),
+            (String::from("0.2"), String::from("0.")),
+            (String::from("1"), String::from("0")),
+            (String::from("2xsin("), String::from("2x")),
+            (String::from("sqrt("), String::from("0")),
+            (String::from("(("), String::from("(")),
+            (String::from("2xans"), String::from("2x")),
+        ];
+
+        for (input, expected_output) in data {
+            assert_eq!(engine.handle_message_delete(input), expected_output);
+        }
+    }
+
+    #[test]
+    fn handle_message_dot_tests() {
+        let engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("0"), String::from("0.")),
+            (String::from("1564"), String::from("1564.")),
+            (String::from("15+"), String::from("15+.")),
+            (String::from("0.226x"), String::from("0.226x.")),
+            (String::from("0.226"), String::from("0.226")),
+            (String::from("10.15+0.226"), String::from("10.15+0.226")),
+            (String::from("10.15+0.226/1."), String::from("10.15+0.226/1.")),
+            (String::from("10.15+26"), String::from("10.15+26.")),
+            (String::from("0.2"), String::from("0.2")),
+            (String::from("1"), String::from("1.")),
+            (String::from("2xπ"), String::from("2xπx.")),
+        ];
+
+        for (input, expected_output) in data {
+            assert_eq!(engine.handle_message_dot(input, ""), expected_output);
+        }
+        assert_eq!(engine.handle_message_dot(String::from("1"), ".5"), "1");
+        assert_eq!(engine.handle_message_dot(String::from("2+1"), "25.5"), "2+1");
+        assert_eq!(engine.handle_message_dot(String::from("1"), "5+2.5"), "1.");
+    }
+
+    #[test]
+    fn handle_message_operator_tests() {
+        let engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("0"), '+', String::from("0+")),
+            (String::from("156+"), '-', String::from("156-")),
+            (String::from("15+"), 'x', String::from("15x")),
+            (String::from("0.226/"), '/', String::from("0.226/")),
+            (String::from("0.226"), '+', String::from("0.226+")),
+            (String::from("0."), '+', String::from("0.+")),
+            (String::from("1"), '+', String::from("1+")),
+            (String::from("2x"), '-', String::from("2x-")),
+            (String::from("2/"), '-', String::from("2/-")),
+            (String::from("("), '-', String::from("(-")),
+            (String::from("("), '+', String::from("(0+")),
+            (String::from("2x-"), '-', String::from("2x-")),
+            (String::from("2x-"), '+', String::from("2+")),
+            (String::from("(-"), 'x', String::from("(0x")),
+            (String::from("-"), '+', String::from("0+")),
+            (String::from("2^"), '-', String::from("2^-")),
+            (String::from("2^"), 'x', String::from("2x")),
+            (String::from("2"), '^', String::from("2^")),
+            (String::from("√"), '-', String::from("√-")),
+            (String::from("2x√"), '+', String::from("2+")),
+        ];
+
+        for (input, op, expected_output) in data {
+            assert_eq!(engine.handle_message_operator(input, op), expected_output);
+        }
+    }
+
+    #[test]
+    fn handle_message_root_tests() {
+        let engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("0"), String::from("√")),
+            (String::from("2x"), String::from("2x√")),
+            (String::from("3"), String::from("3√")),
+            (String::from("("), String::from("(√")),
+        ];
+
+        for (input, expected_output) in data {
+            assert_eq!(engine.handle_message_root(input), expected_output);
+        }
+    }
+
+    #[test]
+    fn handle_message_function_tests() {
+        let engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("0"), "sin", String::from("sin(")),
+            (String::from("2"), "cos", String::from("2xcos(")),
+            (String::from("(1)"), "ln", String::from("(1)xln(")),
+            (String::from("2+"), "exp", String::from("2+exp(")),
+            (String::from("sqrt("), "abs", String::from("sqrt(abs(")),
+        ];
+
+        for (input, name, expected_output) in data {
+            assert_eq!(engine.handle_message_function(input, name), expected_output);
+        }
+    }
+
+    #[test]
+    fn handle_message_constant_tests() {
+        let engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("0"), "π", String::from("π")),
+            (String::from("2"), "π", String::from("2xπ")),
+            (String::from("e"), "e", String::from("exe")),
+            (String::from("sin("), "π", String::from("sin(π")),
+            (String::from("(1)"), "e", String::from("(1)xe")),
+        ];
+
+        for (input, name, expected_output) in data {
+            assert_eq!(engine.handle_message_constant(input, name), expected_output);
+        }
+    }
+
+    #[test]
+    fn handle_message_negate_tests() {
+        let engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("0"), String::from("-")),
+            (String::from("-"), String::from("0")),
+            (String::from("5"), String::from("-5")),
+            (String::from("-5"), String::from("5")),
+            (String::from("2x3.5"), String::from("2x-3.5")),
+            (String::from("2x-3.5"), String::from("2x3.5")),
+            (String::from("5-3"), String::from("5--3")),
+            (String::from("5--3"), String::from("5-3")),
+            (String::from("2x"), String::from("2x-")),
+            (String::from("("), String::from("(-")),
+            (String::from("2x(1+2)"), String::from("2x-(1+2)")),
+            (String::from("-(1+(2))"), String::from("(1+(2))")),
+            (String::from("2x√9"), String::from("2x-√9")),
+            (String::from("2x-√9"), String::from("2x√9")),
+            (String::from("3√8"), String::from("3√-8")),
+            (String::from("2xsin(30)"), String::from("2x-sin(30)")),
+            (String::from("2xπ"), String::from("2x-π")),
+            (String::from("-e"), String::from("e")),
+            (String::from("3⌟1⌟2"), String::from("-3⌟1⌟2")),
+            (String::from("2x-7⌟2"), String::from("2x7⌟2")),
+            (String::from("2xans"), String::from("2x-ans")),
+            (String::from("exe"), String::from("ex-e")),
+        ];
+
+        for (input, expected_output) in data {
+            assert_eq!(engine.handle_message_negate(input), expected_output);
+        }
+    }
+
+    #[test]
+    fn format_result_output_tests() {
+        let engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("0"), String::from("0 ")),
+            (String::from("156+"), String::from("156 + ")),
+            (String::from("15-"), String::from("15 - ")),
+            (String::from("15x"), String::from("15 x ")),
+            (String::from("1(15"), String::from("1 x (15 ")),
+            (String::from("15)1"), String::from("15) x 1 ")),
+            (String::from("0.226/"), String::from("0.226 / ")),
+            (String::from("0.226/15+1-"), String::from("0.226 / 15 + 1 - ")),
+            (String::from("(0.226/(15+1))-"), String::from(" (0.226 / (15 + 1) ) - ")),
+            (String::from("0.226666666"), String::from("0.226666666 ")),
+            (String::from("0.0"), String::from("0.0 ")),
+            (String::from("()"), String::from(" (1) ")),
+            (String::from("1000000"), String::from("1 000 000 ")),
+            (String::from("1000000.55"), String::from("1 000 000.55 ")),
+            (String::from("1000000.55555555"), String::from("1 000 000.55555555 ")),
+            (String::from("1000000.55555555+.155555"), String::from("1 000 000.55555555 + 0.155555 ")),
+            (String::from("100000.55555555+.155555/123456+1500.1568"), String::from("100 000.55555555 + 0.155555 / 123 456 + 1 500.1568 ")),
+            (String::from("100000.55555555+(.155555/123456)+1500.1568"), String::from("100 000.55555555 + (0.155555 / 123 456) + 1 500.1568 ")),
+            (String::from("-5+3"), String::from("-5 + 3 ")),
+            (String::from("2x-3000"), String::from("2 x -3 000 ")),
+            (String::from("2x(-.5)"), String::from("2 x (-0.5) ")),
+            (String::from("5--3"), String::from("5 - -3 ")),
+            (String::from("2^10"), String::from("2 ^ 10 ")),
+            (String::from("√16+3√27"), String::from("√16 + 3√27 ")),
+            (String::from("2x(√-4.5)"), String::from("2 x (√-4.5) ")),
+            (String::from("2xsin(30)"), String::from("2 x sin(30) ")),
+            (String::from("sqrt(1000)+exp(1)"), String::from("sqrt(1 000) + exp(1) ")),
+            (String::from("2xexp(ln(2)"), String::from("2 x exp(ln(2) ")),
+            (String::from("2xπ+e"), String::from("2 x π + e ")),
+            (String::from("1234⌟1⌟2000"), String::from("1 234⌟1⌟2 000 ")),
+            (String::from("-7⌟2x2"), String::from("-7⌟2 x 2 ")),
+        ];
+
+        for (input, expected_output) in data {
+            let output = engine.format_result_output(input);
+
+            assert_eq!(output, expected_output);
+        }
+    }
+
+    #[test]
+    fn handle_message_parentheses_tests() {
+        let engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("0"), '(', String::from("(")),
+            (String::from("156+"), '(', String::from("156+(")),
+            (String::from("15-"), '(', String::from("15-(")),
+            (String::from("(15-"), ')', String::from("(15)")),
+            (String::from("(15x"), '(', String::from("(15x(")),
+            (String::from("0.226/"), '(', String::from("0.226/(")),
+            (String::from("0.226/15+1-"), ')', String::from("0.226/15+1-")),
+            (String::from("(0.226666666"), ')', String::from("(0.226666666)")),
+            (String::from("((0.0"), ')', String::from("((0.0)")),
+            (String::from("(1000000)"), ')', String::from("(1000000)")),
+            (String::from("((1000000.55"), ')', String::from("((1000000.55)")),
+            (String::from("(2-"), ')', String::from("(2)")),
+            (String::from("(2x-"), ')', String::from("(2)")),
+            (String::from("e"), '(', String::from("ex(")),
+
+        ];
+
+        for (input, par, expected_output) in data {
+            assert_eq!(engine.handle_message_parentheses(input, par), expected_output);
+        }
+    }
+
+    #[test]
+    fn handle_message_sequence_tests() {
+        let data = vec![
+            (vec![MessageEmit::Number(1), MessageEmit::Operator('+'), MessageEmit::Number(2)], "1 + 2 ", ""),
+            (vec![MessageEmit::Number(1), MessageEmit::Operator('+'), MessageEmit::Number(2), MessageEmit::Equal], "3 ", "1 + 2 =  "),
+            (vec![MessageEmit::Parentheses('('), MessageEmit::Number(2), MessageEmit::Operator('x'), MessageEmit::Equal], "2 ", " (2) =  "),
+            (vec![MessageEmit::Parentheses('('), MessageEmit::Equal], "0 ", "Empty expression  "),
+            (vec![MessageEmit::Number(4), MessageEmit::Equal, MessageEmit::Clear], "0 ", ""),
+            (vec![MessageEmit::Number(2), MessageEmit::Operator('x'), MessageEmit::Operator('-'), MessageEmit::Number(3), MessageEmit::Equal], "-6 ", "2 x -3 =  "),
+            (vec![MessageEmit::Number(2), MessageEmit::Operator('^'), MessageEmit::Number(3), MessageEmit::Operator('^'), MessageEmit::Number(2), MessageEmit::Equal], "512 ", "2 ^ 3 ^ 2 =  "),
+            (vec![MessageEmit::Root, MessageEmit::Number(9), MessageEmit::Operator('+'), MessageEmit::Number(1), MessageEmit::Equal], "4 ", "√9 + 1 =  "),
+            (vec![MessageEmit::Function("sin"), MessageEmit::Number(9), MessageEmit::Number(0), MessageEmit::Equal], "1 ", "sin(90) =  "),
+            (vec![MessageEmit::Function("abs"), MessageEmit::Operator('-'), MessageEmit::Number(2), MessageEmit::Equal], "2 ", "abs(-2) =  "),
+            (vec![MessageEmit::Number(2), MessageEmit::Function("log"), MessageEmit::Equal], "2 ", "2 =  "),
+            (vec![MessageEmit::Function("ln"), MessageEmit::Constant("e"), MessageEmit::Equal], "1 ", "ln(e) =  "),
+            (vec![MessageEmit::Number(7), MessageEmit::Negate, MessageEmit::Operator('+'), MessageEmit::Number(2), MessageEmit::Equal], "-5 ", "-7 + 2 =  "),
+        ];
+
+        for (messages, expected_display, expected_history) in data {
+            let mut engine = CalculatorEngine::new();
+
+            for msg in messages {
+                engine.handle_message(msg);
+            }
+            assert_eq!(engine.display(), expected_display);
+            assert_eq!(engine.history(), expected_history);
+        }
+    }
+
+    #[test]
+    fn angle_mode_tests() {
+        let mut engine = CalculatorEngine::new();
+
+        assert_eq!(engine.memory_line(), "DEG   ");
+        engine.handle_message(MessageEmit::CycleAngleMode);
+        assert_eq!(engine.angle_mode(), AngleMode::Radians);
+
+        for msg in [MessageEmit::Function("cos"), MessageEmit::Number(0), MessageEmit::Equal] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "1 ");
+        assert_eq!(engine.memory_line(), "RAD   cos(0) =  ");
+
+        engine.handle_message(MessageEmit::CycleAngleMode);
+        engine.handle_message(MessageEmit::CycleAngleMode);
+        assert_eq!(engine.angle_mode(), AngleMode::Degrees);
+    }
+
+    #[test]
+    fn number_mode_tests() {
+        let mut engine = CalculatorEngine::new();
+        let one_third = [MessageEmit::Number(1), MessageEmit::Operator('/'), MessageEmit::Number(3), MessageEmit::Equal];
+
+        assert_eq!(engine.number_mode(), NumberMode::Decimal);
+        engine.handle_message(MessageEmit::SetNumberMode(NumberMode::Rational));
+        for msg in one_third.clone() {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "1⌟3 ");
+
+        engine.handle_message(MessageEmit::SetNumberMode(NumberMode::Integer));
+        engine.handle_message(MessageEmit::Clear);
+        for msg in one_third {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "0 ");
+        assert_eq!(engine.number_mode(), NumberMode::Integer);
+    }
+
+    #[test]
+    fn fraction_form_tests() {
+        let mut engine = CalculatorEngine::new();
+
+        engine.handle_message(MessageEmit::SetNumberMode(NumberMode::Rational));
+        for msg in [MessageEmit::Number(7), MessageEmit::Operator('/'), MessageEmit::Number(2), MessageEmit::Equal] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "7⌟2 ");
+
+        let data = vec![
+            (FractionForm::Mixed, "3⌟1⌟2 "),
+            (FractionForm::Decimal, "3.5 "),
+            (FractionForm::Fraction, "7⌟2 "),
+        ];
+
+        for (expected_form, expected_display) in data {
+            engine.handle_message(MessageEmit::CycleFractionForm);
+            assert_eq!(engine.fraction_form(), expected_form);
+            assert_eq!(engine.display(), expected_display);
+        }
+
+        // The result keeps reading as one number
+        for msg in [MessageEmit::Operator('^'), MessageEmit::Number(2), MessageEmit::Equal] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "49⌟4 ");
+
+        // Once editing goes on, the toggle only changes the form of later results
+        engine.handle_message(MessageEmit::Operator('+'));
+        engine.handle_message(MessageEmit::CycleFractionForm);
+        assert_eq!(engine.display(), "49⌟4 + ");
+        assert_eq!(engine.fraction_form(), FractionForm::Mixed);
+    }
+
+    #[test]
+    fn history_tests() {
+        let mut engine = CalculatorEngine::new();
+        let messages = [
+            MessageEmit::Number(1), MessageEmit::Number(2), MessageEmit::Number(0), MessageEmit::Number(0),
+            MessageEmit::Operator('x'), MessageEmit::Number(2), MessageEmit::Equal,
+            MessageEmit::Operator('-'), MessageEmit::Number(4), MessageEmit::Equal,
+            MessageEmit::Operator('/'), MessageEmit::Number(0), MessageEmit::Equal,
+            MessageEmit::Clear,
+        ];
+
+        for msg in messages {
+            engine.handle_message(msg);
+        }
+        let entries: Vec<(&str, &str)> = engine
+            .calculations()
+            .entries()
+            .iter()
+            .map(|entry| (entry.expression.as_str(), entry.result.as_str()))
+            .collect();
+        assert_eq!(entries, vec![("1 200 x 2", "2 400"), ("2 400 - 4", "2 396")]);
+
+        let data = vec![
+            (MessageEmit::RecallExpression(0), "1 200 x 2 "),
+            (MessageEmit::RecallResult(1), "2 396 "),
+            (MessageEmit::RecallResult(2), "2 396 "),
+        ];
+
+        for (msg, expected_display) in data {
+            engine.handle_message(msg);
+            assert_eq!(engine.display(), expected_display);
+        }
+
+        engine.handle_message(MessageEmit::ClearHistory);
+        assert!(engine.calculations().is_empty());
+    }
+
+    #[test]
+    fn session_tests() {
+        let mut engine = CalculatorEngine::new();
+
+        for msg in [MessageEmit::Number(6), MessageEmit::Operator('x'), MessageEmit::Number(7), MessageEmit::Equal, MessageEmit::Operator('+')] {
+            engine.handle_message(msg);
+        }
+
+        let mut restored = CalculatorEngine::new();
+        restored.restore(engine.session());
+
+        assert_eq!(restored.display(), "42 + ");
+        assert_eq!(restored.history(), "6 x 7 =  ");
+        assert_eq!(restored.calculations(), engine.calculations());
+
+        let mut session = engine.session();
+        session.display = String::from("1234");
+        restored.restore(session);
+        assert_eq!(restored.display(), "1 234 ");
+    }
+
+    #[test]
+    fn memory_tests() {
+        let mut engine = CalculatorEngine::new();
+        let data = vec![
+            (vec![MessageEmit::Number(5), MessageEmit::MemoryAdd], "5", "5 "),
+            (vec![MessageEmit::Operator('x'), MessageEmit::Number(2), MessageEmit::MemoryAdd], "15", "5 x 2 "),
+            (vec![MessageEmit::Operator('+'), MessageEmit::MemorySubtract], "5", "5 x 2 + "),
+            (vec![MessageEmit::MemoryStore], "10", "5 x 2 + "),
+            (vec![MessageEmit::MemoryRecall], "10", "5 x 2 + 10 "),
+            (vec![MessageEmit::MemoryRecall], "10", "5 x 2 + 10 x 10 "),
+            (vec![MessageEmit::Clear, MessageEmit::MemoryRecall], "10", "10 "),
+            (vec![MessageEmit::MemoryClear], "0", "10 "),
+        ];
+
+        for (messages, expected_register, expected_display) in data {
+            for msg in messages {
+                engine.handle_message(msg);
+            }
+            assert_eq!(engine.memory_register(), expected_register);
+            assert_eq!(engine.display(), expected_display);
+        }
+    }
+
+    #[test]
+    fn memory_line_tests() {
+        let mut engine = CalculatorEngine::new();
+
+        for msg in [MessageEmit::Number(3), MessageEmit::MemoryStore] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.memory_line(), "DEG   M   ");
+
+        for msg in [MessageEmit::Operator('/'), MessageEmit::MemoryAdd] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.memory_register(), "6");
+
+        for msg in [MessageEmit::Number(0), MessageEmit::MemoryAdd] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.memory_register(), "6");
+        assert_eq!(engine.memory_line(), "DEG   M   Division by zero  ");
+
+        engine.handle_message(MessageEmit::MemoryClear);
+        assert_eq!(engine.memory_line(), "DEG   Division by zero  ");
+    }
+
+    #[test]
+    fn stores_tests() {
+        let mut engine = CalculatorEngine::new();
+
+        for msg in [MessageEmit::Number(4), MessageEmit::Operator('+'), MessageEmit::Number(2), MessageEmit::StoreTo('A')] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.store('A'), Some("6"));
+        assert_eq!(engine.store('B'), None);
+
+        for msg in [MessageEmit::Clear, MessageEmit::Number(2), MessageEmit::RecallFrom('A'), MessageEmit::RecallFrom('B')] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "2 x 6 ");
+
+        let mut restored = CalculatorEngine::new();
+        restored.restore(engine.session());
+        assert_eq!(restored.store('A'), Some("6"));
+    }
+
+    #[test]
+    fn variables_tests() {
+        let mut engine = CalculatorEngine::new();
+        let data: Vec<(&str, Result<&str, &str>)> = vec![
+            ("rate = 0.07", Ok("0.07")),
+            ("1200 x rate", Ok("84")),
+            ("base = 1000 x 3", Ok("3 000")),
+            ("rate = rate + 0.01", Ok("0.08")),
+            ("base x rate", Ok("240")),
+            ("2 x vat", Err("Undefined variable 'vat'")),
+            ("pi = 3", Err("Cannot assign to 'pi' at 0")),
+            ("rate = ", Err("Unexpected end at 5")),
+        ];
+
+        for (line, expected_result) in data {
+            assert_eq!(engine.evaluate_typed(line), expected_result.map(String::from).map_err(String::from), "Failed test for {line:?}");
+        }
+        assert_eq!(engine.variables(), [("base", String::from("3 000")), ("rate", String::from("0.08"))]);
+        assert_eq!(engine.calculations().entries().last().unwrap().expression, "base x rate");
+        assert_eq!(engine.calculations().entries()[2].expression, "base = 1 000 x 3");
+
+        for msg in [MessageEmit::Clear, MessageEmit::Number(2), MessageEmit::RecallVariable(String::from("rate")), MessageEmit::RecallVariable(String::from("vat"))] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "2 x rate ");
+
+        let mut restored = CalculatorEngine::new();
+        restored.restore(engine.session());
+        assert_eq!(restored.variables(), engine.variables());
+        assert_eq!(restored.evaluate_typed("rate x 100"), Ok(String::from("8")));
+    }
+
+    #[test]
+    fn variable_names_with_x_tests() {
+        let mut engine = CalculatorEngine::new();
+        let data: Vec<(&str, Result<&str, &str>)> = vec![
+            ("tax = 0.2", Ok("0.2")),
+            ("xmin = 5", Ok("5")),
+            ("100 x tax", Ok("20")),
+            ("tax x 10", Ok("2")),
+            ("tax x xmin", Ok("1")),
+            ("2 x taxi", Err("Undefined variable 'taxi'")),
+        ];
+
+        for (line, expected_result) in data {
+            assert_eq!(engine.evaluate_typed(line), expected_result.map(String::from).map_err(String::from), "Failed test for {line:?}");
+        }
+        assert_eq!(engine.calculations().entries().last().unwrap().expression, "tax x xmin");
+
+        for msg in [MessageEmit::Clear, MessageEmit::RecallVariable(String::from("tax")), MessageEmit::Operator('+'), MessageEmit::Number(1)] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "tax + 1 ");
+
+        for msg in [MessageEmit::Clear, MessageEmit::RecallVariable(String::from("xmin")), MessageEmit::Operator('x'), MessageEmit::Equal] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "5 ");
+    }
+
+    #[test]
+    fn user_functions_tests() {
+        let mut engine = CalculatorEngine::new();
+        let data: Vec<(&str, Result<&str, &str>)> = vec![
+            ("f(x, y) = x^2 + y", Ok("f(x, y) defined")),
+            ("f(3, 4)", Ok("13")),
+            ("2 x f(1, 2) + 1", Ok("7")),
+            ("g(n) = f(n, n) x 2", Ok("g(n) defined")),
+            ("g(2)", Ok("12")),
+            ("f(1)", Err("'f' takes 2 arguments, not 1")),
+            ("h(2)", Err("Undefined function 'h'")),
+            ("r(n) = r(n) + 1", Ok("r(n) defined")),
+            ("r(1)", Err("Too many nested calls")),
+            ("f(x, x) = x", Err("Parameter 'x' repeated at 4")),
+            ("d(x) = 2x", Ok("d(x) defined")),
+            ("d(3)", Ok("6")),
+            ("k(x, y) = 3x - yx", Ok("k(x, y) defined")),
+            ("k(5, 2)", Ok("5")),
+        ];
+
+        for (line, expected_result) in data {
+            assert_eq!(engine.evaluate_typed(line), expected_result.map(String::from).map_err(String::from), "Failed test for {line:?}");
+        }
+        assert_eq!(engine.function_definitions(), "d(x) = 2 x x\nf(x, y) = x ^ 2 + y\ng(n) = f(n, n) x 2\nk(x, y) = 3 x x - y x x\nr(n) = r(n) + 1");
+
+        engine.handle_message(MessageEmit::Clear);
+        engine.handle_message(MessageEmit::Paste(String::from("f(3, 4)")));
+        assert_eq!(engine.display(), "f(3, 4) ");
+
+        let mut restored = CalculatorEngine::new();
+        assert_eq!(restored.restore(engine.session()), Vec::<String>::new());
+        assert_eq!(restored.function_definitions(), engine.function_definitions());
+        assert_eq!(restored.evaluate_typed("g(1)"), Ok(String::from("4")));
+
+        let mut session = engine.session();
+        session.functions.push(String::from("h(n)=n+"));
+        session.variables.push((String::from("rate"), String::from("1/")));
+        assert_eq!(CalculatorEngine::new().restore(session), ["Cannot restore rate=1/: Unexpected end at 2", "Cannot restore h(n)=n+: Unexpected end at 7"]);
+
+        restored.handle_message(MessageEmit::DefineFunctions(String::from("sq(x) = x x x\n\ncube(x) = sq(x) x x")));
+        assert_eq!(restored.function_definitions(), "cube(x) = sq(x) x x\nsq(x) = x x x");
+        assert_eq!(restored.evaluate_typed("cube(2)"), Ok(String::from("8")));
+
+        restored.handle_message(MessageEmit::DefineFunctions(String::from("sq(x) = x x x\n1 + 1")));
+        assert_eq!(restored.memory_line(), "DEG   Line 2: not a function definition  ");
+        assert_eq!(restored.function_definitions(), "cube(x) = sq(x) x x\nsq(x) = x x x");
+    }
+
+    #[test]
+    fn variables_rational_mode_tests() {
+        let mut engine = CalculatorEngine::new();
+
+        engine.handle_message(MessageEmit::SetNumberMode(NumberMode::Rational));
+        assert_eq!(engine.evaluate_typed("third = 1/3"), Ok(String::from("1⌟3")));
+        assert_eq!(engine.evaluate_typed("third x 3"), Ok(String::from("1")));
+
+        let mut restored = CalculatorEngine::new();
+        restored.restore(engine.session());
+        assert_eq!(restored.evaluate_typed("third x 6"), Ok(String::from("2")));
+    }
+
+    #[test]
+    fn chaining_tests() {
+        let data = vec![
+            (vec![MessageEmit::Number(9), MessageEmit::Operator('/'), MessageEmit::Number(2), MessageEmit::Equal, MessageEmit::Number(1)], "1 "),
+            (vec![MessageEmit::Number(9), MessageEmit::Operator('/'), MessageEmit::Number(2), MessageEmit::Equal, MessageEmit::Operator('x'), MessageEmit::Number(2)], "4.5 x 2 "),
+            (vec![MessageEmit::Number(9), MessageEmit::Equal, MessageEmit::Dot, MessageEmit::Number(5)], "0.5 "),
+            (vec![MessageEmit::Number(9), MessageEmit::Equal, MessageEmit::Constant("π")], "π "),
+            (vec![MessageEmit::Number(9), MessageEmit::Equal, MessageEmit::Function("sqrt")], "sqrt( "),
+            (vec![MessageEmit::Number(9), MessageEmit::Equal, MessageEmit::Negate, MessageEmit::Operator('+'), MessageEmit::Number(1)], "-9 + 1 "),
+            (vec![MessageEmit::Number(9), MessageEmit::Equal, MessageEmit::CycleAngleMode, MessageEmit::Number(2)], "2 "),
+            (vec![MessageEmit::Number(9), MessageEmit::Equal, MessageEmit::Delete, MessageEmit::Number(2)], "2 "),
+            (vec![MessageEmit::Number(9), MessageEmit::Equal, MessageEmit::Equal, MessageEmit::Number(2)], "2 "),
+        ];
+
+        for (messages, expected_display) in data {
+            let mut engine = CalculatorEngine::new();
+
+            for msg in messages {
+                engine.handle_message(msg);
+            }
+            assert_eq!(engine.display(), expected_display);
+        }
+    }
+
+    #[test]
+    fn answer_tests() {
+        let mut engine = CalculatorEngine::new();
+        let data = vec![
+            (vec![MessageEmit::Answer, MessageEmit::Operator('+'), MessageEmit::Number(3), MessageEmit::Equal], "3 "),
+            (vec![MessageEmit::Number(2), MessageEmit::Answer], "2 x ans "),
+            (vec![MessageEmit::Equal], "6 "),
+            (vec![MessageEmit::Answer, MessageEmit::Operator('/'), MessageEmit::Number(4), MessageEmit::Equal], "1.5 "),
+            (vec![MessageEmit::SetNumberMode(NumberMode::Rational), MessageEmit::Number(1), MessageEmit::Operator('/'), MessageEmit::Number(3), MessageEmit::Equal], "1⌟3 "),
+            (vec![MessageEmit::CycleFractionForm, MessageEmit::CycleFractionForm, MessageEmit::Answer, MessageEmit::Operator('x'), MessageEmit::Number(3), MessageEmit::Equal], "1 "),
+        ];
+
+        for (messages, expected_display) in data {
+            for msg in messages {
+                engine.handle_message(msg);
+            }
+            assert_eq!(engine.display(), expected_display);
+        }
+
+        let mut restored = CalculatorEngine::new();
+        restored.restore(engine.session());
+        for msg in [MessageEmit::Answer, MessageEmit::Operator('+'), MessageEmit::Number(1), MessageEmit::Equal] {
+            restored.handle_message(msg);
+        }
+        assert_eq!(restored.display(), "2 ");
+    }
+
+    #[test]
+    fn undo_tests() {
+        let mut engine = CalculatorEngine::new();
+        let data = vec![
+            (vec![MessageEmit::Number(1), MessageEmit::Operator('+'), MessageEmit::Number(2), MessageEmit::Equal], "3 ", "1 + 2 =  "),
+            (vec![MessageEmit::Clear], "0 ", ""),
+            (vec![MessageEmit::Undo], "3 ", "1 + 2 =  "),
+            (vec![MessageEmit::Undo], "1 + 2 ", ""),
+            (vec![MessageEmit::Undo, MessageEmit::Undo], "1 ", ""),
+            (vec![MessageEmit::Redo], "1 + ", ""),
+            (vec![MessageEmit::Redo, MessageEmit::Redo, MessageEmit::Redo, MessageEmit::Redo], "0 ", ""),
+            (vec![MessageEmit::Undo, MessageEmit::Number(4)], "4 ", "1 + 2 =  "),
+            (vec![MessageEmit::Redo], "4 ", "1 + 2 =  "),
+            (vec![MessageEmit::CycleAngleMode, MessageEmit::Undo], "3 ", "1 + 2 =  "),
+            (vec![MessageEmit::Operator('x')], "3 x ", "1 + 2 =  "),
+        ];
+
+        for (messages, expected_display, expected_history) in data {
+            for msg in messages {
+                engine.handle_message(msg);
+            }
+            assert_eq!(engine.display(), expected_display);
+            assert_eq!(engine.history(), expected_history);
+        }
+
+        let mut engine = CalculatorEngine::new();
+        for _ in 0..MAX_UNDO + 1 {
+            engine.handle_message(MessageEmit::Number(1));
+        }
+        for _ in 0..MAX_UNDO + 1 {
+            engine.handle_message(MessageEmit::Undo);
+        }
+        assert_eq!(engine.display(), "1 ");
+    }
+
+    #[test]
+    fn caret_tests() {
+        let data = vec![
+            (vec![MessageEmit::Number(1), MessageEmit::Number(2), MessageEmit::CaretLeft, MessageEmit::Number(5)], "152 ", 2),
+            (vec![MessageEmit::Number(1), MessageEmit::Operator('+'), MessageEmit::Number(2), MessageEmit::CaretHome, MessageEmit::Number(3)], "31 + 2 ", 1),
+            (vec![MessageEmit::Number(1), MessageEmit::Operator('+'), MessageEmit::Number(2), MessageEmit::CaretLeft, MessageEmit::Delete], "12 ", 1),
+            (vec![MessageEmit::Number(1), MessageEmit::Operator('+'), MessageEmit::Number(2), MessageEmit::CaretLeft, MessageEmit::Operator('x')], "1 x 2 ", 3),
+            (vec![MessageEmit::Number(1), MessageEmit::Number(2), MessageEmit::Number(3), MessageEmit::Number(4), MessageEmit::CaretHome, MessageEmit::CaretRight, MessageEmit::Number(0)], "10 234 ", 2),
+            (vec![MessageEmit::Constant("π"), MessageEmit::CaretHome, MessageEmit::Number(2)], "2 x π ", 3),
+            (vec![MessageEmit::Number(2), MessageEmit::CaretHome, MessageEmit::Constant("π")], "π x 2 ", 4),
+            (vec![MessageEmit::Function("sin"), MessageEmit::Number(9), MessageEmit::CaretLeft, MessageEmit::CaretLeft, MessageEmit::Number(2)], "2 x sin(9 ", 3),
+            (vec![MessageEmit::Function("sin"), MessageEmit::Number(9), MessageEmit::CaretLeft, MessageEmit::Delete], "9 ", 0),
+            (vec![MessageEmit::Function("sin"), MessageEmit::CaretHome, MessageEmit::CaretRight, MessageEmit::Number(9)], "sin(9 ", 5),
+            (vec![MessageEmit::Number(7), MessageEmit::CaretHome, MessageEmit::Negate], "-7 ", 1),
+            (vec![MessageEmit::Number(7), MessageEmit::CaretLeft, MessageEmit::CaretLeft, MessageEmit::CaretRight, MessageEmit::CaretRight], "7 ", 1),
+            (vec![MessageEmit::CaretHome, MessageEmit::Number(7)], "7 ", 1),
+            (vec![MessageEmit::Number(1), MessageEmit::Operator('+'), MessageEmit::Number(2), MessageEmit::CaretHome, MessageEmit::Equal], "3 ", 1),
+            (vec![MessageEmit::Number(1), MessageEmit::Operator('+'), MessageEmit::Number(2), MessageEmit::CaretHome, MessageEmit::CaretEnd, MessageEmit::Number(3)], "1 + 23 ", 6),
+            (vec![MessageEmit::Number(7), MessageEmit::Number(6), MessageEmit::CaretHome, MessageEmit::Equal, MessageEmit::Number(1)], "1 ", 1),
+            (vec![MessageEmit::Number(1), MessageEmit::Number(2), MessageEmit::CaretLeft, MessageEmit::Equal, MessageEmit::Root], "√ ", 3),
+            (vec![MessageEmit::Number(1), MessageEmit::Number(2), MessageEmit::CaretLeft, MessageEmit::Equal, MessageEmit::Root, MessageEmit::Number(9)], "√9 ", 4),
+            (vec![MessageEmit::Constant("π"), MessageEmit::Number(2), MessageEmit::CaretHome, MessageEmit::Equal, MessageEmit::Constant("π"), MessageEmit::Number(1)], "π x 1 ", 6),
+            (vec![MessageEmit::Number(1), MessageEmit::Dot, MessageEmit::Number(5), MessageEmit::CaretLeft, MessageEmit::CaretLeft, MessageEmit::Dot], "1.5 ", 1),
+            (vec![MessageEmit::Number(1), MessageEmit::Dot, MessageEmit::Number(5), MessageEmit::CaretHome, MessageEmit::Dot], "1.5 ", 0),
+            (vec![MessageEmit::Number(1), MessageEmit::Number(5), MessageEmit::CaretLeft, MessageEmit::Dot], "1.5 ", 2),
+            (
+                vec![
+                    MessageEmit::Operator('»'), MessageEmit::Number(9), MessageEmit::Constant("π"), MessageEmit::Constant("e"),
+                    MessageEmit::CaretHome, MessageEmit::CaretRight, MessageEmit::Operator('|'), MessageEmit::Dot,
+                ],
+                "0 | 0. » 9 x π x e ",
+                6,
+            ),
+        ];
+
+        for (messages, expected_display, expected_caret) in data {
+            let mut engine = CalculatorEngine::new();
+
+            for msg in messages {
+                engine.handle_message(msg);
+            }
+            assert_eq!(engine.display(), expected_display);
+            assert_eq!(engine.caret(), expected_caret, "Failed test for display {expected_display:?}");
+        }
+    }
+
+    #[test]
+    fn copy_text_tests() {
+        let mut engine = CalculatorEngine::new();
+
+        let data = vec![
+            (String::from("1 234 567.5 "), "1234567.5"),
+            (String::from("12 x 3 + "), "12x3+"),
+            (String::from("7⌟2 "), "7/2"),
+            (String::from("-3⌟1⌟2 "), "-3 1/2"),
+            (String::from("0 "), "0"),
+        ];
+
+        for (display, expected_text) in data {
+            engine.result_output = display;
+
+            assert_eq!(engine.copy_text(), expected_text);
+        }
+    }
+
+    #[test]
+    fn paste_tests() {
+        // Pasted numbers are not capped like typed ones
+        let long_number = "1".repeat(128);
+        let long_display = format!("11{} ", " 111".repeat(42));
+        let data = vec![
+            (vec![MessageEmit::Paste(String::from("1,234.5"))], "1 234.5 ", ""),
+            (vec![MessageEmit::Paste(String::from(" 2 * (3 + 4)\n"))], "2 x (3 + 4) ", ""),
+            (vec![MessageEmit::Paste(String::from("6÷2×3"))], "6 / 2 x 3 ", ""),
+            (vec![MessageEmit::Number(2), MessageEmit::Operator('+'), MessageEmit::Paste(String::from("1 000"))], "2 + 1 000 ", ""),
+            (vec![MessageEmit::Number(2), MessageEmit::Paste(String::from("pi"))], "2 x pi ", ""),
+            (vec![MessageEmit::Number(2), MessageEmit::Equal, MessageEmit::Paste(String::from("5"))], "5 ", "2 =  "),
+            (vec![MessageEmit::Number(2), MessageEmit::Paste(String::from("=SUM(A1)"))], "2 ", "Cannot paste: Unexpected '=' at 0  "),
+            (vec![MessageEmit::Paste(String::from("3+"))], "0 ", "Cannot paste: Unexpected end at 2  "),
+            (vec![MessageEmit::Paste(String::from("rate=1200"))], "rate = 1 200 ", ""),
+            (vec![MessageEmit::Paste(String::new())], "0 ", "Cannot paste: Empty expression  "),
+            (vec![MessageEmit::Paste(long_number)], long_display.as_str(), ""),
+            (
+                vec![MessageEmit::Root, MessageEmit::Dot, MessageEmit::Parentheses('('), MessageEmit::Number(3), MessageEmit::Paste(String::from("π×2"))],
+                "√. (3 x π x 2 ",
+                "",
+            ),
+        ];
+
+        for (messages, expected_display, expected_history) in data {
+            let mut engine = CalculatorEngine::new();
+
+            for msg in messages {
+                engine.handle_message(msg);
+            }
+            assert_eq!(engine.display(), expected_display);
+            assert_eq!(engine.history(), expected_history);
+        }
+    }
+
+    #[test]
+    fn percent_tests() {
+        let data = vec![
+            (vec![MessageEmit::Number(2), MessageEmit::Number(0), MessageEmit::Number(0), MessageEmit::Operator('+'), MessageEmit::Number(1), MessageEmit::Number(0), MessageEmit::Percent], "200 + 10% "),
+            (vec![MessageEmit::Number(2), MessageEmit::Operator('+'), MessageEmit::Percent], "2 + "),
+            (vec![MessageEmit::Parentheses('('), MessageEmit::Percent], " ( "),
+            (vec![MessageEmit::Number(1), MessageEmit::Number(0), MessageEmit::Number(0), MessageEmit::Number(0), MessageEmit::Percent, MessageEmit::Operator('x'), MessageEmit::Number(2)], "1 000% x 2 "),
+            (vec![MessageEmit::Number(5), MessageEmit::Percent, MessageEmit::Number(3)], "5% x 3 "),
+            (vec![MessageEmit::Number(5), MessageEmit::Percent, MessageEmit::Constant("π")], "5% x π "),
+            (vec![MessageEmit::Number(2), MessageEmit::Operator('+'), MessageEmit::Number(5), MessageEmit::Percent, MessageEmit::Negate], "2 + -5% "),
+            (vec![MessageEmit::Number(2), MessageEmit::Operator('+'), MessageEmit::Number(5), MessageEmit::Percent, MessageEmit::Negate, MessageEmit::Negate], "2 + 5% "),
+            (vec![MessageEmit::Number(2), MessageEmit::Number(0), MessageEmit::Number(0), MessageEmit::Operator('-'), MessageEmit::Number(5), MessageEmit::Percent, MessageEmit::Equal], "190 "),
+            (vec![MessageEmit::Number(5), MessageEmit::Number(0), MessageEmit::Percent, MessageEmit::Delete], "50 "),
+        ];
+
+        for (messages, expected_display) in data {
+            let mut engine = CalculatorEngine::new();
+
+            for msg in messages {
+                engine.handle_message(msg);
+            }
+            assert_eq!(engine.display(), expected_display);
+        }
+    }
+
+    #[test]
+    fn programmer_mode_tests() {
+        let data = vec![
+            (vec![MessageEmit::RadixPrefix("0x"), MessageEmit::Number(1), MessageEmit::Number(15)], "0x1F ", ""),
+            (vec![MessageEmit::Number(15), MessageEmit::Number(10)], "0xFA ", ""),
+            (vec![MessageEmit::Number(2), MessageEmit::Number(11)], "2 x 0xB ", ""),
+            (vec![MessageEmit::RadixPrefix("0b"), MessageEmit::Number(1), MessageEmit::Number(2), MessageEmit::Number(0)], "0b10 ", ""),
+            (vec![MessageEmit::RadixPrefix("0o"), MessageEmit::Number(7), MessageEmit::Number(8), MessageEmit::Dot], "0o7 ", ""),
+            (vec![MessageEmit::RadixPrefix("0x"), MessageEmit::RadixPrefix("0b")], "0b ", ""),
+            (vec![MessageEmit::RadixPrefix("0b"), MessageEmit::Operator('+')], "0 + ", ""),
+            (vec![MessageEmit::Number(1), MessageEmit::Number(0), MessageEmit::Operator('x'), MessageEmit::Number(5)], "10 x 5 ", ""),
+            (vec![MessageEmit::Number(1), MessageEmit::Number(2), MessageEmit::Number(3), MessageEmit::Number(4), MessageEmit::Operator('+'), MessageEmit::RadixPrefix("0b"), MessageEmit::Number(1), MessageEmit::Number(0), MessageEmit::Number(1), MessageEmit::Number(1)], "1 234 + 0b1011 ", ""),
+            (vec![MessageEmit::Number(15), MessageEmit::Number(15), MessageEmit::Negate], "-0xFF ", ""),
+            (vec![MessageEmit::Number(15), MessageEmit::Parentheses('(')], "0xF x ( ", ""),
+            (
+                vec![MessageEmit::RadixPrefix("0x"), MessageEmit::Number(1), MessageEmit::Number(15), MessageEmit::Operator('+'), MessageEmit::RadixPrefix("0o"), MessageEmit::Number(1), MessageEmit::Equal],
+                "32 ",
+                "HEX 20  DEC 32  OCT 40  BIN 100000  ",
+            ),
+            (vec![MessageEmit::Number(7), MessageEmit::Operator('/'), MessageEmit::Number(2), MessageEmit::Equal], "3 ", "HEX 3  DEC 3  OCT 3  BIN 11  "),
+        ];
+
+        for (messages, expected_display, expected_history) in data {
+            let mut engine = CalculatorEngine::new();
+
+            engine.handle_message(MessageEmit::SetNumberMode(NumberMode::Programmer));
+            for msg in messages.clone() {
+                engine.handle_message(msg);
+            }
+            assert_eq!(engine.display(), expected_display, "Failed test for {messages:?}");
+            assert_eq!(engine.history(), expected_history, "Failed test for {messages:?}");
+        }
+
+        // Outside programmer mode, hexadecimal keys do nothing
+        let mut engine = CalculatorEngine::new();
+
+        engine.handle_message(MessageEmit::Number(12));
+        engine.handle_message(MessageEmit::RadixPrefix("0x"));
+        assert_eq!(engine.display(), "0 ");
+
+        // A multi-byte character before `x` is not mistaken for a prefix
+        let mut engine = CalculatorEngine::new();
+
+        for msg in [MessageEmit::Constant("π"), MessageEmit::Operator('x'), MessageEmit::Number(8), MessageEmit::SetNumberMode(NumberMode::Programmer), MessageEmit::Number(1)] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "π x 81 ");
+    }
+
+    #[test]
+    fn bitwise_tests() {
+        let data = vec![
+            (vec![MessageEmit::Number(6), MessageEmit::Operator('&'), MessageEmit::Number(3)], "6 & 3 ", ""),
+            (vec![MessageEmit::Number(6), MessageEmit::Operator('&'), MessageEmit::Number(3), MessageEmit::Equal], "2 ", "HEX 2  DEC 2  OCT 2  BIN 10  "),
+            (vec![MessageEmit::Not, MessageEmit::Number(0), MessageEmit::Equal], "-1 ", "HEX FFFFFFFFFFFFFFFF  DEC -1  OCT 1777777777777777777777  BIN 1111111111111111111111111111111111111111111111111111111111111111  "),
+            (vec![MessageEmit::Number(5), MessageEmit::Operator('«'), MessageEmit::Number(2)], "5 « 2 ", ""),
+            (vec![MessageEmit::Number(5), MessageEmit::Operator('&'), MessageEmit::Operator('-'), MessageEmit::Number(1)], "5 & -1 ", ""),
+            (vec![MessageEmit::Number(5), MessageEmit::Operator('|'), MessageEmit::Operator('⊻')], "5 ⊻ ", ""),
+            (vec![MessageEmit::Number(5), MessageEmit::Not, MessageEmit::Operator('+')], "5 + ", ""),
+            (vec![MessageEmit::Number(1), MessageEmit::Operator('+'), MessageEmit::Not, MessageEmit::Number(2), MessageEmit::Negate], "1 + ~-2 ", ""),
+            (vec![MessageEmit::Paste(String::from("1 << 4 >> 2"))], "1 « 4 » 2 ", ""),
+        ];
+
+        for (messages, expected_display, expected_history) in data {
+            let mut engine = CalculatorEngine::new();
+
+            engine.handle_message(MessageEmit::SetNumberMode(NumberMode::Programmer));
+            for msg in messages.clone() {
+                engine.handle_message(msg);
+            }
+            assert_eq!(engine.display(), expected_display, "Failed test for {messages:?}");
+            assert_eq!(engine.history(), expected_history, "Failed test for {messages:?}");
+        }
+    }
+
+    #[test]
+    fn word_size_tests() {
+        let mut engine = CalculatorEngine::new();
+
+        engine.handle_message(MessageEmit::SetNumberMode(NumberMode::Programmer));
+        assert_eq!(engine.memory_line(), "i64   ");
+
+        engine.handle_message(MessageEmit::CycleWordSize);
+        engine.handle_message(MessageEmit::ToggleSigned);
+        assert_eq!(engine.word(), Word { bits: 8, signed: false });
+
+        for msg in [MessageEmit::Number(2), MessageEmit::Number(5), MessageEmit::Number(5), MessageEmit::Operator('+'), MessageEmit::Number(1), MessageEmit::Equal] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "0 ");
+        assert_eq!(engine.memory_line(), "u8   Overflow   HEX 0  DEC 0  OCT 0  BIN 0  ");
+
+        engine.handle_message(MessageEmit::ToggleSigned);
+        for msg in [MessageEmit::Clear, MessageEmit::Number(15), MessageEmit::Number(15), MessageEmit::Equal] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "-1 ");
+        assert_eq!(engine.memory_line(), "i8   Overflow   HEX FF  DEC -1  OCT 377  BIN 11111111  ");
+
+        // Other modes keep the angle mode on the line
+        engine.handle_message(MessageEmit::SetNumberMode(NumberMode::Decimal));
+        assert!(engine.memory_line().starts_with("DEG   "));
+    }
+}
@@ -0,0 +1,78 @@
+/// Oldest entries are dropped past this count.
+pub const MAX_ENTRIES: usize = 100;
+
+/// An evaluated expression and its result, both as shown on the display.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HistoryEntry {
+    pub expression: String,
+    pub result: String,
+}
+
+/// Every successful calculation, oldest first.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History { entries: vec![] }
+    }
+
+    pub fn push(&mut self, expression: &str, result: &str) {
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.entries.push(HistoryEntry {
+            expression: expression.trim().to_string(),
+            result: result.trim().to_string(),
+        });
+    }
+
+    pub fn get(&self, index: usize) -> Option<&HistoryEntry> {
+        self.entries.get(index)
+    }
+
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{History, HistoryEntry, MAX_ENTRIES};
+
+    #[test]
+    fn history_tests() {
+        let mut history = History::new();
+
+        assert!(history.is_empty());
+        history.push("1 + 2 ", "3 ");
+        history.push("3 x 4 ", "12 ");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get(0), Some(&HistoryEntry { expression: String::from("1 + 2"), result: String::from("3") }));
+        assert_eq!(history.entries()[1].result, "12");
+        assert_eq!(history.get(2), None);
+
+        for n in 0..MAX_ENTRIES {
+            history.push(&n.to_string(), &n.to_string());
+        }
+        assert_eq!(history.len(), MAX_ENTRIES);
+        assert_eq!(history.get(0).unwrap().expression, "0");
+
+        history.clear();
+        assert!(history.is_empty());
+    }
+}
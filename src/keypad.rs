@@ -0,0 +1,158 @@
+use crate::calculator::settings;
+use crate::number::NumberMode;
+
+/// Arrangement of the keypad, switchable at runtime from the menu.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Layout {
+    #[default]
+    Basic,
+    Scientific,
+    /// Hexadecimal digits, radix prefixes and bitwise operators, shown in
+    /// programmer mode whichever layout is chosen.
+    Programmer,
+}
+
+impl Layout {
+    /// The layout shown for this choice in number `mode`.
+    pub fn in_mode(self, mode: NumberMode) -> Layout {
+        match mode {
+            NumberMode::Programmer => Layout::Programmer,
+            _ => self,
+        }
+    }
+
+    /// Keys row by row, as understood by `MyButton::new`; an empty key leaves
+    /// its slot empty.
+    pub fn keys(&self) -> &'static [&'static str] {
+        match self {
+            Layout::Basic => &[
+                "MC", "MR", "M+", "M-",
+                "n", "%", "r", "MS",
+                "(", ")", "AC", "d",
+                "7", "8", "9", "/",
+                "4", "5", "6", "x",
+                "1", "2", "3", "-",
+                ".", "0", "=", "+",
+            ],
+            Layout::Scientific => &[
+                "MC", "MR", "M+", "M-", "MS",
+                "sin", "cos", "tan", "drg", "d",
+                "ln", "log", "exp", "abs", "AC",
+                "pi", "e", "^", "r", "n",
+                "(", "7", "8", "9", "/",
+                ")", "4", "5", "6", "x",
+                "%", "1", "2", "3", "-",
+                "frac", ".", "0", "=", "+",
+            ],
+            Layout::Programmer => &[
+                "MC", "MR", "M+", "M-", "MS", "d",
+                "word", "sign", "0x", "0b", "0o", "AC",
+                "&", "|", "⊻", "~", "(", ")",
+                "«", "»", "↺", "↻", "^", "n",
+                "E", "F", "7", "8", "9", "/",
+                "C", "D", "4", "5", "6", "x",
+                "A", "B", "1", "2", "3", "-",
+                "", "", "", "0", "=", "+",
+            ],
+        }
+    }
+
+    pub fn columns(&self) -> i32 {
+        match self {
+            Layout::Basic => 4,
+            Layout::Scientific => 5,
+            Layout::Programmer => 6,
+        }
+    }
+
+    pub fn rows(&self) -> i32 {
+        self.keys().len() as i32 / self.columns()
+    }
+
+    pub fn window_size(&self) -> (i32, i32) {
+        (
+            self.columns() * settings::BUTTON_WIDTH,
+            settings::RESULT_SCREEN_HEIGHT + self.rows() * settings::BUTTON_HEIGHT,
+        )
+    }
+
+    /// Position of every non-empty key, relative to the window. In `rpn`
+    /// mode, the stack keys take the place of the parentheses, delete and `=`.
+    pub fn key_positions(&self, rpn: bool) -> Vec<(&'static str, (i32, i32))> {
+        self.keys()
+            .iter()
+            .enumerate()
+            .filter(|(_, key)| !key.is_empty())
+            .map(|(index, key)| {
+                let pos_x = (index as i32 % self.columns()) * settings::BUTTON_WIDTH;
+                let pos_y = (index as i32 / self.columns()) * settings::BUTTON_HEIGHT + settings::RESULT_SCREEN_HEIGHT;
+
+                (if rpn { rpn_key(key) } else { key }, (pos_x, pos_y))
+            })
+            .collect()
+    }
+}
+
+/// The key of RPN mode in the slot of `key`.
+fn rpn_key(key: &'static str) -> &'static str {
+    match key {
+        "(" => "swap",
+        ")" => "roll",
+        "d" => "drop",
+        "=" => "enter",
+        key => key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Layout;
+    use crate::calculator::settings;
+    use crate::number::NumberMode;
+
+    #[test]
+    fn layout_tests() {
+        let data = vec![
+            (Layout::Basic, (320, 450)),
+            (Layout::Scientific, (400, 500)),
+            (Layout::Programmer, (480, 500)),
+        ];
+
+        for (layout, expected_size) in data {
+            assert_eq!(layout.keys().len() as i32 % layout.columns(), 0, "{layout:?} has an incomplete row");
+            assert_eq!(layout.window_size(), expected_size);
+
+            for (key, (pos_x, pos_y)) in layout.key_positions(false) {
+                assert!(pos_x + settings::BUTTON_WIDTH <= expected_size.0, "{key} is out of the window");
+                assert!(pos_y + settings::BUTTON_HEIGHT <= expected_size.1, "{key} is out of the window");
+            }
+        }
+    }
+
+    #[test]
+    fn in_mode_tests() {
+        let data = vec![
+            (Layout::Basic, NumberMode::Decimal, Layout::Basic),
+            (Layout::Scientific, NumberMode::Integer, Layout::Scientific),
+            (Layout::Basic, NumberMode::Programmer, Layout::Programmer),
+            (Layout::Scientific, NumberMode::Programmer, Layout::Programmer),
+        ];
+
+        for (layout, mode, expected_layout) in data {
+            assert_eq!(layout.in_mode(mode), expected_layout);
+        }
+    }
+
+    #[test]
+    fn rpn_key_positions_tests() {
+        for layout in [Layout::Basic, Layout::Scientific, Layout::Programmer] {
+            let keys: Vec<&str> = layout.key_positions(true).into_iter().map(|(key, _)| key).collect();
+
+            for (key, rpn_key) in [("(", "swap"), (")", "roll"), ("d", "drop"), ("=", "enter")] {
+                assert!(!keys.contains(&key), "{layout:?} still has {key} in RPN mode");
+                assert!(keys.contains(&rpn_key), "{layout:?} has no {rpn_key} in RPN mode");
+            }
+            assert_eq!(layout.key_positions(true).len(), layout.key_positions(false).len());
+        }
+    }
+}
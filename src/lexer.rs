@@ -0,0 +1,461 @@
+use num_bigint::BigInt;
+
+use crate::ast::is_reserved_name;
+use crate::decimal::Decimal;
+use crate::number::RADIX_PREFIXES;
+use crate::operations::ParseError;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TokenKind {
+    Number(Decimal),
+    Plus,
+    Minus,
+    Times,
+    Divide,
+    Power,
+    Root,
+    Percent,
+    And,
+    Or,
+    Xor,
+    Not,
+    ShiftLeft,
+    ShiftRight,
+    RotateLeft,
+    RotateRight,
+    Identifier(String),
+    /// `=` after the name of a variable or function being defined.
+    Assign,
+    /// Separates the arguments of a user function.
+    Comma,
+    OpenParenthese,
+    CloseParenthese,
+    FractionBar,
+}
+
+/// A token and the byte offset where it starts in the input.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub offset: usize,
+}
+
+/// With `radix_prefixes`, `0x1F`, `0o17` and `0b1010` are numbers rather
+/// than zero times a name, as long as a valid digit follows the prefix.
+pub fn tokenize(input: &str, radix_prefixes: bool) -> Result<Vec<Token>, ParseError> {
+    tokenize_with(input, radix_prefixes, &|_| false)
+}
+
+/// Like `tokenize`, `is_defined` telling the names of variables and user
+/// functions. Names can hold an `x`, as in `tax` or `xmin`, which is the
+/// multiplication sign only after an operand, as in `2xtax`, or after a
+/// known name, as in `ratexbase` or `taxx2`. After the `=` of a
+/// definition, its parameters are known names, and a parameter `x` with no
+/// operand after it is multiplied, as in `f(x)=2x`.
+pub fn tokenize_with(input: &str, radix_prefixes: bool, is_defined: &dyn Fn(&str) -> bool) -> Result<Vec<Token>, ParseError> {
+    let mut tokens: Vec<Token> = vec![];
+    let mut chars = input.char_indices().peekable();
+    let mut parameters: Vec<String> = vec![];
+
+    while let Some((offset, char)) = chars.next() {
+        let is_known = |name: &str| name == "x" || is_reserved_name(name) || is_defined(name) || parameters.iter().any(|parameter| parameter == name);
+        let multiplies_x = |rest: &str| parameters.iter().any(|parameter| parameter == "x") && !starts_operand(rest);
+
+        let kind = match char {
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            'x' if !expects_operand(tokens.last()) => {
+                tokens.push(Token { kind: TokenKind::Times, offset });
+
+                if !multiplies_x(&input[offset + 1..]) {
+                    continue;
+                }
+                TokenKind::Identifier(String::from("x"))
+            },
+            '/' => TokenKind::Divide,
+            '^' => TokenKind::Power,
+            '√' => TokenKind::Root,
+            '%' => TokenKind::Percent,
+            '&' => TokenKind::And,
+            '|' => TokenKind::Or,
+            '⊻' => TokenKind::Xor,
+            '~' => TokenKind::Not,
+            '«' => TokenKind::ShiftLeft,
+            '»' => TokenKind::ShiftRight,
+            '↺' => TokenKind::RotateLeft,
+            '↻' => TokenKind::RotateRight,
+            'π' => TokenKind::Identifier(String::from("π")),
+            '=' => {
+                parameters = definition_parameters(&tokens);
+                TokenKind::Assign
+            },
+            ',' => TokenKind::Comma,
+            '(' => TokenKind::OpenParenthese,
+            ')' => TokenKind::CloseParenthese,
+            FRACTION_BAR => TokenKind::FractionBar,
+            c if c.is_ascii_alphabetic() => {
+                let run = read_letters(&input[offset..]);
+                let rest = &input[offset + run.len()..];
+                let mut start = 0;
+
+                for sign in split_names(run, rest, &is_known) {
+                    tokens.push(Token { kind: TokenKind::Identifier(run[start..sign].to_string()), offset: offset + start });
+                    tokens.push(Token { kind: TokenKind::Times, offset: offset + sign });
+                    start = sign + 1;
+                }
+                for _ in 1..run.len() {
+                    chars.next();
+                }
+                if start < run.len() {
+                    tokens.push(Token { kind: TokenKind::Identifier(run[start..].to_string()), offset: offset + start });
+                } else if multiplies_x(rest) {
+                    tokens.push(Token { kind: TokenKind::Identifier(String::from("x")), offset: offset + start - 1 });
+                }
+                continue;
+            },
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut end = offset + 1;
+
+                match read_radix_prefix(&input[offset..]).filter(|_| radix_prefixes) {
+                    Some(radix) => {
+                        // Skip the prefix letter, then read digits of that radix
+                        chars.next();
+                        while let Some((index, _)) = chars.next_if(|(_, c)| c.is_digit(radix)) {
+                            end = index + 1;
+                        }
+                        read_radix_number(&input[offset..end], radix, offset)?
+                    },
+                    None => {
+                        while let Some((index, _)) = chars.next_if(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                            end = index + 1;
+                        }
+                        read_number(&input[offset..end], offset)?
+                    },
+                }
+            },
+            token => return Err(ParseError::UnexpectedToken { token, offset }),
+        };
+
+        tokens.push(Token { kind, offset });
+    }
+
+    Ok(tokens)
+}
+
+/// Whether the token after `previous` starts an operand, as at the start of
+/// the input or after an operator.
+fn expects_operand(previous: Option<&Token>) -> bool {
+    !matches!(
+        previous.map(|token| &token.kind),
+        Some(TokenKind::Number(_) | TokenKind::Identifier(_) | TokenKind::CloseParenthese | TokenKind::Percent)
+    )
+}
+
+/// Whether `input` starts with an operand, a sign aside, rather than an
+/// operator or the end.
+fn starts_operand(input: &str) -> bool {
+    input.starts_with(|c: char| c.is_ascii_alphanumeric() || ['.', '(', 'π', '√', '~'].contains(&c))
+}
+
+/// The parameters when `tokens` are the head of a definition, as `f(x,y)`.
+fn definition_parameters(tokens: &[Token]) -> Vec<String> {
+    let [Token { kind: TokenKind::Identifier(_), .. }, Token { kind: TokenKind::OpenParenthese, .. }, list @ .., Token { kind: TokenKind::CloseParenthese, .. }] = tokens else {
+        return vec![];
+    };
+    let mut parameters: Vec<String> = vec![];
+
+    for (index, token) in list.iter().enumerate() {
+        match &token.kind {
+            TokenKind::Identifier(name) if index % 2 == 0 => parameters.push(name.clone()),
+            TokenKind::Comma if index % 2 == 1 => (),
+            _ => return vec![],
+        }
+    }
+
+    parameters
+}
+
+/// Binary operators on the bits of integers, in the order of `TokenKind`.
+pub const BITWISE_OPERATORS: [char; 7] = ['&', '|', '⊻', '«', '»', '↺', '↻'];
+
+/// Separates the terms of `7⌟2` (seven halves) and `3⌟1⌟2` (three and a half).
+pub const FRACTION_BAR: char = '⌟';
+
+/// Names are runs of ASCII letters, `x` included.
+fn read_letters(input: &str) -> &str {
+    let end = input
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(input.len());
+
+    &input[..end]
+}
+
+/// Offsets of the `x`s of `run` that are multiplication signs, `rest`
+/// following it. A known name is kept whole, then `run` is cut into known
+/// names if it can be, a last `x` being the sign when an operand or the end
+/// follows. Otherwise, the `x` after the longest known name is the sign.
+fn split_names(run: &str, rest: &str, is_known: &dyn Fn(&str) -> bool) -> Vec<usize> {
+    if is_known(run) {
+        return vec![];
+    }
+    if let Some(signs) = known_names(run, is_known) {
+        return signs;
+    }
+    if let Some(head) = run.strip_suffix('x').filter(|_| rest.is_empty() || starts_operand(rest)) {
+        if let Some(mut signs) = known_names(head, is_known).filter(|_| !head.is_empty()) {
+            signs.push(head.len());
+            return signs;
+        }
+    }
+
+    let longest = run
+        .match_indices('x')
+        .map(|(sign, _)| sign)
+        .rfind(|sign| *sign > 0 && *sign + 1 < run.len() && is_known(&run[..*sign]));
+
+    match longest {
+        Some(sign) => [sign].into_iter().chain(split_names(&run[sign + 1..], rest, is_known).into_iter().map(|offset| sign + 1 + offset)).collect(),
+        None => vec![],
+    }
+}
+
+/// Offsets of the `x`s cutting `run` into known names only, if it can be,
+/// the longest first.
+fn known_names(run: &str, is_known: &dyn Fn(&str) -> bool) -> Option<Vec<usize>> {
+    if is_known(run) {
+        return Some(vec![]);
+    }
+
+    for (sign, _) in run.match_indices('x').collect::<Vec<_>>().into_iter().rev() {
+        let (name, rest) = (&run[..sign], &run[sign + 1..]);
+
+        if name.is_empty() || rest.is_empty() || !is_known(name) {
+            continue;
+        }
+        if let Some(signs) = known_names(rest, is_known) {
+            return Some([sign].into_iter().chain(signs.into_iter().map(|offset| sign + 1 + offset)).collect());
+        }
+    }
+
+    None
+}
+
+fn read_number(literal: &str, offset: usize) -> Result<TokenKind, ParseError> {
+    match literal.parse::<Decimal>() {
+        Ok(value) => Ok(TokenKind::Number(value)),
+        Err(_) => Err(ParseError::InvalidNumber {
+            literal: literal.to_string(),
+            offset
+        }),
+    }
+}
+
+/// Radix of the literal starting `input`, if it has a prefix followed by a digit.
+fn read_radix_prefix(input: &str) -> Option<u32> {
+    RADIX_PREFIXES
+        .iter()
+        .find(|(prefix, radix)| {
+            input.starts_with(prefix) && input[prefix.len()..].starts_with(|c: char| c.is_digit(*radix))
+        })
+        .map(|(_, radix)| *radix)
+}
+
+fn read_radix_number(literal: &str, radix: u32, offset: usize) -> Result<TokenKind, ParseError> {
+    match BigInt::parse_bytes(&literal.as_bytes()[2..], radix) {
+        Some(value) => Ok(TokenKind::Number(Decimal::from(value))),
+        None => Err(ParseError::InvalidNumber {
+            literal: literal.to_string(),
+            offset
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize, tokenize_with, Token, TokenKind};
+    use crate::decimal::Decimal;
+
+    #[test]
+    fn tokenize_tests() {
+        let data: Vec<(&str, Vec<(TokenKind, usize)>)> = vec![
+            ("1+1", vec![(TokenKind::Number(Decimal::from(1)), 0), (TokenKind::Plus, 1), (TokenKind::Number(Decimal::from(1)), 2)]),
+            ("12.5x(3-.5)", vec![
+                (TokenKind::Number("12.5".parse().unwrap()), 0),
+                (TokenKind::Times, 4),
+                (TokenKind::OpenParenthese, 5),
+                (TokenKind::Number(Decimal::from(3)), 6),
+                (TokenKind::Minus, 7),
+                (TokenKind::Number("0.5".parse().unwrap()), 8),
+                (TokenKind::CloseParenthese, 10),
+            ]),
+            ("100/4", vec![(TokenKind::Number(Decimal::from(100)), 0), (TokenKind::Divide, 3), (TokenKind::Number(Decimal::from(4)), 4)]),
+            ("2^-3", vec![(TokenKind::Number(Decimal::from(2)), 0), (TokenKind::Power, 1), (TokenKind::Minus, 2), (TokenKind::Number(Decimal::from(3)), 3)]),
+            ("3√8+√4", vec![
+                (TokenKind::Number(Decimal::from(3)), 0),
+                (TokenKind::Root, 1),
+                (TokenKind::Number(Decimal::from(8)), 4),
+                (TokenKind::Plus, 5),
+                (TokenKind::Root, 6),
+                (TokenKind::Number(Decimal::from(4)), 9),
+            ]),
+            ("2xsin(30)", vec![
+                (TokenKind::Number(Decimal::from(2)), 0),
+                (TokenKind::Times, 1),
+                (TokenKind::Identifier(String::from("sin")), 2),
+                (TokenKind::OpenParenthese, 5),
+                (TokenKind::Number(Decimal::from(30)), 6),
+                (TokenKind::CloseParenthese, 8),
+            ]),
+            ("2xπ+e", vec![
+                (TokenKind::Number(Decimal::from(2)), 0),
+                (TokenKind::Times, 1),
+                (TokenKind::Identifier(String::from("π")), 2),
+                (TokenKind::Plus, 4),
+                (TokenKind::Identifier(String::from("e")), 5),
+            ]),
+            ("expxln", vec![
+                (TokenKind::Identifier(String::from("exp")), 0),
+                (TokenKind::Times, 3),
+                (TokenKind::Identifier(String::from("ln")), 4),
+            ]),
+            ("~5&3|1«2", vec![
+                (TokenKind::Not, 0),
+                (TokenKind::Number(Decimal::from(5)), 1),
+                (TokenKind::And, 2),
+                (TokenKind::Number(Decimal::from(3)), 3),
+                (TokenKind::Or, 4),
+                (TokenKind::Number(Decimal::from(1)), 5),
+                (TokenKind::ShiftLeft, 6),
+                (TokenKind::Number(Decimal::from(2)), 8),
+            ]),
+            ("3⌟1⌟2", vec![
+                (TokenKind::Number(Decimal::from(3)), 0),
+                (TokenKind::FractionBar, 1),
+                (TokenKind::Number(Decimal::from(1)), 4),
+                (TokenKind::FractionBar, 5),
+                (TokenKind::Number(Decimal::from(2)), 8),
+            ]),
+            ("rate=0.07", vec![
+                (TokenKind::Identifier(String::from("rate")), 0),
+                (TokenKind::Assign, 4),
+                (TokenKind::Number("0.07".parse().unwrap()), 5),
+            ]),
+            ("f(x,2)=xx2", vec![
+                (TokenKind::Identifier(String::from("f")), 0),
+                (TokenKind::OpenParenthese, 1),
+                (TokenKind::Identifier(String::from("x")), 2),
+                (TokenKind::Comma, 3),
+                (TokenKind::Number(Decimal::from(2)), 4),
+                (TokenKind::CloseParenthese, 5),
+                (TokenKind::Assign, 6),
+                (TokenKind::Identifier(String::from("x")), 7),
+                (TokenKind::Times, 8),
+                (TokenKind::Number(Decimal::from(2)), 9),
+            ]),
+            ("0x1F", vec![
+                (TokenKind::Number(Decimal::from(0)), 0),
+                (TokenKind::Times, 1),
+                (TokenKind::Number(Decimal::from(1)), 2),
+                (TokenKind::Identifier(String::from("F")), 3),
+            ]),
+            ("", vec![]),
+        ];
+
+        for (input, expected_tokens) in data {
+            let expected_tokens: Vec<Token> = expected_tokens
+                .into_iter()
+                .map(|(kind, offset)| Token { kind, offset })
+                .collect();
+
+            assert_eq!(tokenize(input, false).unwrap(), expected_tokens, "Failed test for input {input}");
+        }
+    }
+
+    #[test]
+    fn tokenize_radix_prefixes_tests() {
+        let data: Vec<(&str, Vec<(TokenKind, usize)>)> = vec![
+            ("0x1F+0b101", vec![
+                (TokenKind::Number(Decimal::from(31)), 0),
+                (TokenKind::Plus, 4),
+                (TokenKind::Number(Decimal::from(5)), 5),
+            ]),
+            ("0o17x0xff", vec![
+                (TokenKind::Number(Decimal::from(15)), 0),
+                (TokenKind::Times, 4),
+                (TokenKind::Number(Decimal::from(255)), 5),
+            ]),
+            ("10x5", vec![(TokenKind::Number(Decimal::from(10)), 0), (TokenKind::Times, 2), (TokenKind::Number(Decimal::from(5)), 3)]),
+            ("0b2", vec![(TokenKind::Number(Decimal::from(0)), 0), (TokenKind::Identifier(String::from("b")), 1), (TokenKind::Number(Decimal::from(2)), 2)]),
+        ];
+
+        for (input, expected_tokens) in data {
+            let expected_tokens: Vec<Token> = expected_tokens
+                .into_iter()
+                .map(|(kind, offset)| Token { kind, offset })
+                .collect();
+
+            assert_eq!(tokenize(input, true).unwrap(), expected_tokens, "Failed test for input {input}");
+        }
+    }
+
+    #[test]
+    fn tokenize_names_with_x_tests() {
+        let is_defined = |name: &str| ["tax", "rate", "base", "xmin"].contains(&name);
+        let name = |name: &str| TokenKind::Identifier(String::from(name));
+
+        let data: Vec<(&str, Vec<(TokenKind, usize)>)> = vec![
+            ("max=2", vec![(name("max"), 0), (TokenKind::Assign, 3), (TokenKind::Number(Decimal::from(2)), 4)]),
+            ("tax", vec![(name("tax"), 0)]),
+            ("xmin", vec![(name("xmin"), 0)]),
+            ("taxx2", vec![(name("tax"), 0), (TokenKind::Times, 3), (TokenKind::Number(Decimal::from(2)), 4)]),
+            ("ratexbase", vec![(name("rate"), 0), (TokenKind::Times, 4), (name("base"), 5)]),
+            ("ratexbasex", vec![(name("rate"), 0), (TokenKind::Times, 4), (name("base"), 5), (TokenKind::Times, 9)]),
+            ("ratexmaxi", vec![(name("rate"), 0), (TokenKind::Times, 4), (name("maxi"), 5)]),
+            ("2xtax", vec![(TokenKind::Number(Decimal::from(2)), 0), (TokenKind::Times, 1), (name("tax"), 2)]),
+            ("expxtax", vec![(name("exp"), 0), (TokenKind::Times, 3), (name("tax"), 4)]),
+            ("f(total)=totalxvat", vec![
+                (name("f"), 0),
+                (TokenKind::OpenParenthese, 1),
+                (name("total"), 2),
+                (TokenKind::CloseParenthese, 7),
+                (TokenKind::Assign, 8),
+                (name("total"), 9),
+                (TokenKind::Times, 14),
+                (name("vat"), 15),
+            ]),
+            ("f(x)=2x", vec![
+                (name("f"), 0),
+                (TokenKind::OpenParenthese, 1),
+                (name("x"), 2),
+                (TokenKind::CloseParenthese, 3),
+                (TokenKind::Assign, 4),
+                (TokenKind::Number(Decimal::from(2)), 5),
+                (TokenKind::Times, 6),
+                (name("x"), 6),
+            ]),
+            ("f(x)=2x3-taxx", vec![
+                (name("f"), 0),
+                (TokenKind::OpenParenthese, 1),
+                (name("x"), 2),
+                (TokenKind::CloseParenthese, 3),
+                (TokenKind::Assign, 4),
+                (TokenKind::Number(Decimal::from(2)), 5),
+                (TokenKind::Times, 6),
+                (TokenKind::Number(Decimal::from(3)), 7),
+                (TokenKind::Minus, 8),
+                (name("tax"), 9),
+                (TokenKind::Times, 12),
+                (name("x"), 12),
+            ]),
+        ];
+
+        for (input, expected_tokens) in data {
+            let expected_tokens: Vec<Token> = expected_tokens
+                .into_iter()
+                .map(|(kind, offset)| Token { kind, offset })
+                .collect();
+
+            assert_eq!(tokenize_with(input, false, &is_defined).unwrap(), expected_tokens, "Failed test for input {input}");
+        }
+    }
+}
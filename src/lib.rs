@@ -1,3 +1,14 @@
+pub mod ast;
 pub mod calculator;
+pub mod cli;
+pub mod decimal;
+pub mod engine;
+pub mod history;
+pub mod keypad;
+pub mod lexer;
+pub mod number;
 pub mod operations;
-pub mod button;
\ No newline at end of file
+pub mod repl;
+pub mod rpn;
+pub mod session;
+pub mod button;
@@ -1,9 +1,48 @@
+use std::io;
+use std::process::ExitCode;
+
 use basic_desktop_calculator::calculator::CalculatorApp;
+use basic_desktop_calculator::cli::{self, Command};
+use basic_desktop_calculator::repl;
 
-fn main() {
-    let mut calculator_app = CalculatorApp::new();
-    
-    calculator_app.init_gui();
-    calculator_app.run();
-}
+fn main() -> ExitCode {
+    let command = match Command::from_args(std::env::args().skip(1), cli::stdin_is_piped()) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{err}\n\n{}", cli::USAGE);
+            return ExitCode::from(2);
+        },
+    };
+
+    let evaluated = match command {
+        Command::Gui => {
+            let mut calculator_app = CalculatorApp::new();
 
+            calculator_app.init_gui();
+            calculator_app.run();
+            return ExitCode::SUCCESS;
+        },
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        },
+        Command::Repl => {
+            if let Err(err) = repl::run() {
+                eprintln!("{err}");
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        },
+        Command::Evaluate(expressions) => cli::evaluate_all(expressions, &mut io::stdout(), &mut io::stderr()),
+        Command::EvaluateStdin => cli::evaluate_lines(io::stdin().lock(), &mut io::stdout(), &mut io::stderr()),
+    };
+
+    match evaluated {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        },
+    }
+}
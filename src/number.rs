@@ -0,0 +1,603 @@
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+use num_bigint::BigInt;
+use num_integer::{Integer, Roots};
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, One, Pow, Signed, ToPrimitive, Zero};
+
+use crate::decimal::{Decimal, MAX_MAGNITUDE};
+use crate::lexer::FRACTION_BAR;
+use crate::operations::ParseError;
+
+/// Arithmetic the evaluator needs from a numeric backend. The `precision`
+/// arguments are significant digits, only inexact backends use them.
+pub trait Number:
+    Sized + Clone + fmt::Display + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Neg<Output = Self>
+{
+    /// Whether the backend only holds integers, its division truncating.
+    const INTEGRAL: bool = false;
+    /// Results past `10^MAX_MAGNITUDE` are out of range.
+    const MAX_MAGNITUDE: f64 = MAX_MAGNITUDE as f64;
+
+    /// Converts a literal or a constant.
+    fn from_decimal(value: &Decimal) -> Result<Self, ParseError>;
+    /// Converts the result of a function only computed on an `f64`.
+    fn from_f64(value: f64) -> Result<Self, ParseError>;
+    fn to_f64(&self) -> f64;
+    /// The value, if it is an integer that fits in an `i64`.
+    fn to_i64(&self) -> Option<i64>;
+    /// The value, if it is an integer.
+    fn to_bigint(&self) -> Option<BigInt>;
+    fn is_zero(&self) -> bool;
+    fn abs(&self) -> Self;
+    fn checked_div(&self, divisor: &Self, precision: u64) -> Result<Self, ParseError>;
+    fn powi(&self, exponent: i64, precision: u64) -> Result<Self, ParseError>;
+    fn sqrt(&self, precision: u64) -> Result<Self, ParseError>;
+    /// Rounds a finished result, or rejects it if it is too large to display.
+    fn finalize(self, precision: u64) -> Result<Self, ParseError>;
+}
+
+/// Backend an expression is evaluated with, picked in the settings.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum NumberMode {
+    /// Binary floating point, `f64`.
+    Float,
+    /// Base-10 with the context precision, `Decimal`.
+    #[default]
+    Decimal,
+    /// Exact fractions, `BigRational`.
+    Rational,
+    /// Arbitrarily large integers, `BigInt`. Division truncates.
+    Integer,
+    /// Integers too, entered as `0x1F`, `0o17` or `0b1010` as well.
+    Programmer,
+}
+
+impl NumberMode {
+    pub const ALL: [NumberMode; 5] = [
+        NumberMode::Float,
+        NumberMode::Decimal,
+        NumberMode::Rational,
+        NumberMode::Integer,
+        NumberMode::Programmer,
+    ];
+
+    pub fn from_name(name: &str) -> Option<NumberMode> {
+        NumberMode::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NumberMode::Float => "Float",
+            NumberMode::Decimal => "Decimal",
+            NumberMode::Rational => "Rational",
+            NumberMode::Integer => "Integer",
+            NumberMode::Programmer => "Programmer",
+        }
+    }
+
+    /// Whether numbers may start with a `0x`, `0o` or `0b` radix prefix.
+    pub fn reads_radix_prefixes(&self) -> bool {
+        *self == NumberMode::Programmer
+    }
+}
+
+/// Prefixes of number literals in programmer mode, with their radix.
+pub const RADIX_PREFIXES: [(&str, u32); 3] = [("0x", 16), ("0o", 8), ("0b", 2)];
+
+/// `value` in the bases of programmer mode, e.g. `HEX 1F  DEC 31  OCT 37  BIN 11111`.
+/// Other bases than decimal show the bits of `word`, so -1 is `FF` in `i8`.
+pub fn format_radixes(value: &BigInt, word: Word) -> String {
+    let bits = word.bit_pattern(value);
+
+    format!(
+        "HEX {}  DEC {value}  OCT {}  BIN {}",
+        bits.to_str_radix(16).to_uppercase(),
+        bits.to_str_radix(8),
+        bits.to_str_radix(2),
+    )
+}
+
+/// Width and signedness of the integers bitwise operators work on, which
+/// results also wrap around to in programmer mode.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Word {
+    pub bits: u32,
+    pub signed: bool,
+}
+
+impl Default for Word {
+    fn default() -> Word {
+        Word { bits: 64, signed: true }
+    }
+}
+
+impl Word {
+    pub const SIZES: [u32; 4] = [8, 16, 32, 64];
+
+    /// `i8` to `i64` when signed, `u8` to `u64` otherwise.
+    pub fn name(&self) -> String {
+        format!("{}{}", if self.signed { 'i' } else { 'u' }, self.bits)
+    }
+
+    pub fn from_name(name: &str) -> Option<Word> {
+        let signed = match name.chars().next()? {
+            'i' => true,
+            'u' => false,
+            _ => return None,
+        };
+        let bits = name[1..].parse::<u32>().ok().filter(|bits| Word::SIZES.contains(bits))?;
+
+        Some(Word { bits, signed })
+    }
+
+    /// The next size, back to 8 bits after 64.
+    pub fn next_size(&self) -> Word {
+        let index = Word::SIZES.iter().position(|bits| *bits == self.bits).unwrap_or_default();
+
+        Word { bits: Word::SIZES[(index + 1) % Word::SIZES.len()], ..*self }
+    }
+
+    /// The lowest `bits` bits of `value` in two's complement, as an unsigned number.
+    pub fn bit_pattern(&self, value: &BigInt) -> BigInt {
+        let mask: BigInt = (BigInt::one() << self.bits) - 1;
+
+        value & mask
+    }
+
+    /// `value` wrapped around to the word, and whether it did not fit.
+    pub fn wrap(&self, value: &BigInt) -> (BigInt, bool) {
+        let mut wrapped = self.bit_pattern(value);
+
+        if self.signed && wrapped.bit(u64::from(self.bits) - 1) {
+            wrapped -= BigInt::one() << self.bits;
+        }
+        let overflowed = wrapped != *value;
+
+        (wrapped, overflowed)
+    }
+
+    /// How far a shift moves bits, no further than out of the word.
+    pub fn shift_amount(&self, amount: &BigInt) -> Result<usize, ParseError> {
+        if amount.is_negative() {
+            return Err(ParseError::OutOfRange);
+        }
+        Ok(ToPrimitive::to_u32(amount).map_or(self.bits, |amount| amount.min(self.bits)) as usize)
+    }
+
+    /// Bits leaving on the left come back on the right. A negative `amount`
+    /// rotates to the right.
+    pub fn rotate_left(&self, value: &BigInt, amount: &BigInt) -> BigInt {
+        let amount = amount.mod_floor(&BigInt::from(self.bits)).to_usize().unwrap_or_default();
+        let bits = self.bit_pattern(value);
+        let rotated = (&bits << amount) | (&bits >> (self.bits as usize - amount));
+
+        self.wrap(&self.bit_pattern(&rotated)).0
+    }
+}
+
+/// How a rational result is written, cycled with the `a→b/c` key.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum FractionForm {
+    /// `7⌟2`
+    #[default]
+    Fraction,
+    /// `3⌟1⌟2`
+    Mixed,
+    /// `3.5`
+    Decimal,
+}
+
+impl FractionForm {
+    pub fn next(&self) -> FractionForm {
+        match self {
+            FractionForm::Fraction => FractionForm::Mixed,
+            FractionForm::Mixed => FractionForm::Decimal,
+            FractionForm::Decimal => FractionForm::Fraction,
+        }
+    }
+}
+
+/// Writes `value` in `form`, in a way the lexer reads back. Integers are
+/// written the same in every form.
+pub fn format_rational(value: &BigRational, form: FractionForm, precision: u64) -> String {
+    if value.is_integer() {
+        return value.numer().to_string();
+    }
+
+    match form {
+        FractionForm::Fraction => format!("{}{FRACTION_BAR}{}", value.numer(), value.denom()),
+        FractionForm::Mixed => {
+            let whole = value.trunc();
+            let fraction = Signed::abs(&(value - &whole));
+
+            if Zero::is_zero(&whole) {
+                format!("{}{FRACTION_BAR}{}", value.numer(), value.denom())
+            } else {
+                format!("{}{FRACTION_BAR}{}{FRACTION_BAR}{}", whole.numer(), fraction.numer(), fraction.denom())
+            }
+        },
+        FractionForm::Decimal => Decimal::from(value.numer().clone())
+            .checked_div(&Decimal::from(value.denom().clone()), precision)
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+/// Largest number of bits of a numerator or a denominator, about
+/// `10^MAX_MAGNITUDE`.
+const MAX_BITS: u64 = (MAX_MAGNITUDE as u64 + 1) * 10 / 3;
+
+/// Largest number of bits of a result in integer mode, about `10^19728`.
+const INTEGER_MAX_BITS: u64 = 1 << 16;
+
+impl Number for f64 {
+    const MAX_MAGNITUDE: f64 = f64::MAX_10_EXP as f64;
+
+    fn from_decimal(value: &Decimal) -> Result<f64, ParseError> {
+        Ok(value.to_f64())
+    }
+
+    fn from_f64(value: f64) -> Result<f64, ParseError> {
+        Ok(value)
+    }
+
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        if self.fract() == 0.0 && f64::abs(*self) < i64::MAX as f64 {
+            Some(*self as i64)
+        } else {
+            None
+        }
+    }
+
+    fn to_bigint(&self) -> Option<BigInt> {
+        if self.fract() == 0.0 {
+            FromPrimitive::from_f64(*self)
+        } else {
+            None
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0.0
+    }
+
+    fn abs(&self) -> f64 {
+        f64::abs(*self)
+    }
+
+    fn checked_div(&self, divisor: &f64, _precision: u64) -> Result<f64, ParseError> {
+        if *divisor == 0.0 {
+            return Err(ParseError::DivisionByZero);
+        }
+        Ok(self / divisor)
+    }
+
+    fn powi(&self, exponent: i64, _precision: u64) -> Result<f64, ParseError> {
+        Ok(self.powf(exponent as f64))
+    }
+
+    fn sqrt(&self, _precision: u64) -> Result<f64, ParseError> {
+        if *self < 0.0 {
+            return Err(ParseError::UndefinedResult);
+        }
+        Ok(f64::sqrt(*self))
+    }
+
+    /// Infinite results overflowed the `f64` range.
+    fn finalize(self, _precision: u64) -> Result<f64, ParseError> {
+        if self.is_nan() {
+            return Err(ParseError::UndefinedResult);
+        }
+        if self.is_infinite() {
+            return Err(ParseError::OutOfRange);
+        }
+        Ok(self)
+    }
+}
+
+impl Number for Decimal {
+    fn from_decimal(value: &Decimal) -> Result<Decimal, ParseError> {
+        Ok(value.clone())
+    }
+
+    fn from_f64(value: f64) -> Result<Decimal, ParseError> {
+        Decimal::from_f64(value).ok_or(ParseError::UndefinedResult)
+    }
+
+    fn to_f64(&self) -> f64 {
+        Decimal::to_f64(self)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Decimal::to_i64(self)
+    }
+
+    fn to_bigint(&self) -> Option<BigInt> {
+        BigInt::from_decimal(self).ok()
+    }
+
+    fn is_zero(&self) -> bool {
+        Decimal::is_zero(self)
+    }
+
+    fn abs(&self) -> Decimal {
+        Decimal::abs(self)
+    }
+
+    fn checked_div(&self, divisor: &Decimal, precision: u64) -> Result<Decimal, ParseError> {
+        Decimal::checked_div(self, divisor, precision).ok_or(ParseError::DivisionByZero)
+    }
+
+    fn powi(&self, exponent: i64, precision: u64) -> Result<Decimal, ParseError> {
+        Ok(Decimal::powi(self, exponent, precision))
+    }
+
+    fn sqrt(&self, precision: u64) -> Result<Decimal, ParseError> {
+        Decimal::sqrt(self, precision).ok_or(ParseError::UndefinedResult)
+    }
+
+    fn finalize(self, precision: u64) -> Result<Decimal, ParseError> {
+        let value = self.round(precision);
+
+        if value.is_out_of_range() {
+            return Err(ParseError::OutOfRange);
+        }
+        Ok(value)
+    }
+}
+
+impl Number for BigRational {
+    fn from_decimal(value: &Decimal) -> Result<BigRational, ParseError> {
+        let (digits, scale) = value.as_bigint_and_scale();
+        let power_of_ten: BigInt = Pow::pow(BigInt::from(10), scale.unsigned_abs());
+
+        if scale < 0 {
+            Ok(BigRational::from_integer(digits * power_of_ten))
+        } else {
+            Ok(BigRational::new(digits, power_of_ten))
+        }
+    }
+
+    /// Goes through `Decimal` so that `0.1` becomes `1/10` and not the
+    /// exact value of the nearest `f64`.
+    fn from_f64(value: f64) -> Result<BigRational, ParseError> {
+        BigRational::from_decimal(&<Decimal as Number>::from_f64(value)?)
+    }
+
+    fn to_f64(&self) -> f64 {
+        ToPrimitive::to_f64(self).unwrap_or(f64::NAN)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        if self.is_integer() {
+            ToPrimitive::to_i64(&self.to_integer())
+        } else {
+            None
+        }
+    }
+
+    fn to_bigint(&self) -> Option<BigInt> {
+        if self.is_integer() {
+            Some(self.to_integer())
+        } else {
+            None
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+
+    fn abs(&self) -> BigRational {
+        Signed::abs(self)
+    }
+
+    fn checked_div(&self, divisor: &BigRational, _precision: u64) -> Result<BigRational, ParseError> {
+        if Zero::is_zero(divisor) {
+            return Err(ParseError::DivisionByZero);
+        }
+        Ok(self / divisor)
+    }
+
+    fn powi(&self, exponent: i64, _precision: u64) -> Result<BigRational, ParseError> {
+        Ok(Pow::pow(self, exponent))
+    }
+
+    /// Exact when both terms are perfect squares, like `√(9/4)`.
+    fn sqrt(&self, precision: u64) -> Result<BigRational, ParseError> {
+        if self.is_negative() {
+            return Err(ParseError::UndefinedResult);
+        }
+
+        let (numer, denom) = (self.numer().sqrt(), self.denom().sqrt());
+
+        if &numer * &numer == *self.numer() && &denom * &denom == *self.denom() {
+            return Ok(BigRational::new(numer, denom));
+        }
+        let value = Decimal::from(self.numer().clone()).checked_div(&Decimal::from(self.denom().clone()), precision);
+
+        BigRational::from_decimal(&Number::sqrt(&value.unwrap_or_default(), precision)?)
+    }
+
+    fn finalize(self, _precision: u64) -> Result<BigRational, ParseError> {
+        if self.numer().bits() > MAX_BITS || self.denom().bits() > MAX_BITS {
+            return Err(ParseError::OutOfRange);
+        }
+        Ok(self)
+    }
+}
+
+impl Number for BigInt {
+    const INTEGRAL: bool = true;
+    const MAX_MAGNITUDE: f64 = INTEGER_MAX_BITS as f64 * std::f64::consts::LOG10_2;
+
+    fn from_decimal(value: &Decimal) -> Result<BigInt, ParseError> {
+        if !value.is_integer() {
+            return Err(ParseError::NotAnInteger);
+        }
+        let (digits, scale) = value.as_bigint_and_scale();
+        let power_of_ten: BigInt = Pow::pow(BigInt::from(10), scale.unsigned_abs());
+
+        // Exact, the value being an integer
+        if scale < 0 {
+            Ok(digits * power_of_ten)
+        } else {
+            Ok(digits / power_of_ten)
+        }
+    }
+
+    /// Truncates towards zero, like the division.
+    fn from_f64(value: f64) -> Result<BigInt, ParseError> {
+        FromPrimitive::from_f64(value.trunc()).ok_or(ParseError::UndefinedResult)
+    }
+
+    fn to_f64(&self) -> f64 {
+        ToPrimitive::to_f64(self).unwrap_or(f64::NAN)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn to_bigint(&self) -> Option<BigInt> {
+        Some(self.clone())
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+
+    fn abs(&self) -> BigInt {
+        Signed::abs(self)
+    }
+
+    fn checked_div(&self, divisor: &BigInt, _precision: u64) -> Result<BigInt, ParseError> {
+        if Zero::is_zero(divisor) {
+            return Err(ParseError::DivisionByZero);
+        }
+        Ok(self / divisor)
+    }
+
+    /// Negative exponents give a fraction, truncated to 0 unless the base is ±1.
+    fn powi(&self, exponent: i64, _precision: u64) -> Result<BigInt, ParseError> {
+        if exponent >= 0 {
+            return Ok(Pow::pow(self, exponent.unsigned_abs()));
+        }
+        if Signed::abs(self).is_one() {
+            return Ok(Pow::pow(self, exponent.unsigned_abs()));
+        }
+        Ok(BigInt::zero())
+    }
+
+    /// Rounds down, `√10` is 3.
+    fn sqrt(&self, _precision: u64) -> Result<BigInt, ParseError> {
+        if self.is_negative() {
+            return Err(ParseError::UndefinedResult);
+        }
+        Ok(Roots::sqrt(self))
+    }
+
+    fn finalize(self, _precision: u64) -> Result<BigInt, ParseError> {
+        if self.bits() > INTEGER_MAX_BITS {
+            return Err(ParseError::OutOfRange);
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_bigint::BigInt;
+    use num_rational::BigRational;
+
+    use super::{format_radixes, format_rational, FractionForm, Number, Word};
+    use crate::decimal::Decimal;
+    use crate::operations::ParseError;
+
+    #[test]
+    fn from_decimal_tests() {
+        let data: Vec<(&str, &str, Result<&str, ParseError>)> = vec![
+            ("0.5", "1/2", Err(ParseError::NotAnInteger)),
+            ("12e3", "12000", Ok("12000")),
+            ("-1.25", "-5/4", Err(ParseError::NotAnInteger)),
+            ("7", "7", Ok("7")),
+            ("3.0", "3", Ok("3")),
+            ("-2.500e1", "-25", Ok("-25")),
+        ];
+
+        for (literal, expected_rational, expected_integer) in data {
+            let value: Decimal = literal.parse().unwrap();
+
+            assert_eq!(BigRational::from_decimal(&value).unwrap().to_string(), expected_rational);
+            assert_eq!(BigInt::from_decimal(&value).map(|value| value.to_string()), expected_integer.map(String::from));
+        }
+        assert_eq!(<BigRational as Number>::from_f64(0.1).unwrap().to_string(), "1/10");
+        assert_eq!(<BigInt as Number>::from_f64(-2.7).unwrap().to_string(), "-2");
+    }
+
+    #[test]
+    fn format_rational_tests() {
+        let data: Vec<((i64, i64), FractionForm, &str)> = vec![
+            ((7, 2), FractionForm::Fraction, "7⌟2"),
+            ((7, 2), FractionForm::Mixed, "3⌟1⌟2"),
+            ((7, 2), FractionForm::Decimal, "3.5"),
+            ((-7, 2), FractionForm::Mixed, "-3⌟1⌟2"),
+            ((-1, 2), FractionForm::Mixed, "-1⌟2"),
+            ((1, 3), FractionForm::Decimal, "0.33333"),
+            ((4, 2), FractionForm::Fraction, "2"),
+            ((-6, 3), FractionForm::Mixed, "-2"),
+        ];
+
+        for ((numer, denom), form, expected_output) in data {
+            let value = BigRational::new(BigInt::from(numer), BigInt::from(denom));
+            assert_eq!(format_rational(&value, form, 5), expected_output, "Failed test for {numer}/{denom} as {form:?}");
+        }
+    }
+
+    #[test]
+    fn format_radixes_tests() {
+        let data: Vec<(i64, Word, &str)> = vec![
+            (31, Word::default(), "HEX 1F  DEC 31  OCT 37  BIN 11111"),
+            (0, Word::default(), "HEX 0  DEC 0  OCT 0  BIN 0"),
+            (-10, Word { bits: 8, signed: true }, "HEX F6  DEC -10  OCT 366  BIN 11110110"),
+            (-1, Word { bits: 16, signed: true }, "HEX FFFF  DEC -1  OCT 177777  BIN 1111111111111111"),
+        ];
+
+        for (value, word, expected_output) in data {
+            assert_eq!(format_radixes(&BigInt::from(value), word), expected_output);
+        }
+    }
+
+    #[test]
+    fn word_tests() {
+        let data: Vec<(Word, i64, i64, bool)> = vec![
+            (Word { bits: 8, signed: false }, 255, 255, false),
+            (Word { bits: 8, signed: false }, 256, 0, true),
+            (Word { bits: 8, signed: false }, -1, 255, true),
+            (Word { bits: 8, signed: true }, 127, 127, false),
+            (Word { bits: 8, signed: true }, 128, -128, true),
+            (Word { bits: 8, signed: true }, -129, 127, true),
+            (Word { bits: 16, signed: true }, -300, -300, false),
+            (Word { bits: 32, signed: false }, 1 << 32, 0, true),
+        ];
+
+        for (word, value, expected_value, expected_overflow) in data {
+            assert_eq!(word.wrap(&BigInt::from(value)), (BigInt::from(expected_value), expected_overflow), "Failed test for {value} as {}", word.name());
+        }
+
+        assert_eq!(Word::from_name("u16"), Some(Word { bits: 16, signed: false }));
+        assert_eq!(Word::from_name("i12"), None);
+        assert_eq!(Word::default().next_size(), Word { bits: 8, signed: true });
+
+        let byte = Word { bits: 8, signed: false };
+        assert_eq!(byte.rotate_left(&BigInt::from(0b1000_0001), &BigInt::from(1)), BigInt::from(0b0000_0011));
+        assert_eq!(byte.rotate_left(&BigInt::from(0b1000_0001), &BigInt::from(-1)), BigInt::from(0b1100_0000));
+        assert_eq!(byte.shift_amount(&BigInt::from(100)), Ok(8));
+    }
+}
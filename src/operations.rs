@@ -1,164 +1,1022 @@
-#[derive(Debug, Clone, Copy)]
-pub struct ParseResult {
-    length: usize,
-    pub value: f64,
-}
+use std::fmt;
 
-pub fn parse_expression(input: &str) -> ParseResult {
-    let mut result = parse_term(input);
-    let mut term = result;
-    let mut iter = input.chars();
+use num_bigint::BigInt;
+use num_rational::BigRational;
 
-    while let Some(char) = iter.nth(term.length) {
-        match char {
-            '+' => {
-                term = parse_term(&input[result.length + 1..]);
+use crate::ast::{is_reserved_name, BinaryOperator, Constant, Context, Expr, Function, Statement, UnaryOperator, UserFunction, ANSWER};
+use crate::decimal::Decimal;
+use crate::lexer::{tokenize_with, Token, TokenKind};
+use crate::number::{Number, NumberMode};
 
-                result.length += term.length + 1;
-                result.value += term.value;
-            },
-            '-' => {
-                term = parse_term(&input[result.length + 1..]);
+#[derive(Debug, Clone)]
+pub struct ParseResult<N = Decimal> {
+    pub value: N,
+}
 
-                result.length += term.length + 1;
-                result.value -= term.value;
-            },
-            _ => break
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParseError {
+    EmptyInput,
+    UnexpectedEnd { offset: usize },
+    UnexpectedToken { token: char, offset: usize },
+    UnbalancedParentheses { offset: usize },
+    InvalidNumber { literal: String, offset: usize },
+    /// A variable used before any value was assigned to it.
+    UndefinedVariable { name: String },
+    /// An assignment to `ans`, a constant or a function.
+    ReservedName { name: String, offset: usize },
+    /// A call of a function the user has not defined.
+    UndefinedFunction { name: String },
+    WrongArgumentCount { name: String, expected: usize, found: usize },
+    /// A parameter named twice in a function definition.
+    DuplicateParameter { name: String, offset: usize },
+    /// User functions called one inside the other past `MAX_CALL_DEPTH`,
+    /// as when a function calls itself.
+    TooManyNestedCalls,
+    /// Parentheses, signs or powers nested past `MAX_NESTING_DEPTH`.
+    TooDeeplyNested { offset: usize },
+    DivisionByZero,
+    /// The result is not a real number, e.g. `√-1` or `ln(0)`.
+    UndefinedResult,
+    OutOfRange,
+    /// A fraction in integer mode.
+    NotAnInteger,
+}
+
+impl ParseError {
+    pub fn offset(&self) -> usize {
+        match self {
+            ParseError::EmptyInput
+            | ParseError::UndefinedVariable { .. }
+            | ParseError::UndefinedFunction { .. }
+            | ParseError::WrongArgumentCount { .. }
+            | ParseError::TooManyNestedCalls
+            | ParseError::DivisionByZero
+            | ParseError::UndefinedResult
+            | ParseError::OutOfRange
+            | ParseError::NotAnInteger => 0,
+            ParseError::UnexpectedEnd { offset }
+            | ParseError::UnexpectedToken { offset, .. }
+            | ParseError::UnbalancedParentheses { offset }
+            | ParseError::InvalidNumber { offset, .. }
+            | ParseError::ReservedName { offset, .. }
+            | ParseError::DuplicateParameter { offset, .. }
+            | ParseError::TooDeeplyNested { offset } => *offset,
         }
     }
-    result
 }
 
-fn parse_term(input: &str) -> ParseResult {
-    let mut result = parse_factor(input);
-    let mut factor = result;
-    let mut iter = input.chars();
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::EmptyInput => write!(f, "Empty expression"),
+            ParseError::UnexpectedEnd { offset } => write!(f, "Unexpected end at {offset}"),
+            ParseError::UnexpectedToken { token, offset } => write!(f, "Unexpected '{token}' at {offset}"),
+            ParseError::UnbalancedParentheses { offset } => write!(f, "Unbalanced parenthese at {offset}"),
+            ParseError::InvalidNumber { literal, offset } => write!(f, "Invalid number '{literal}' at {offset}"),
+            ParseError::UndefinedVariable { name } => write!(f, "Undefined variable '{name}'"),
+            ParseError::ReservedName { name, offset } => write!(f, "Cannot assign to '{name}' at {offset}"),
+            ParseError::UndefinedFunction { name } => write!(f, "Undefined function '{name}'"),
+            ParseError::WrongArgumentCount { name, expected: 1, found } => write!(f, "'{name}' takes 1 argument, not {found}"),
+            ParseError::WrongArgumentCount { name, expected, found } => write!(f, "'{name}' takes {expected} arguments, not {found}"),
+            ParseError::DuplicateParameter { name, offset } => write!(f, "Parameter '{name}' repeated at {offset}"),
+            ParseError::TooManyNestedCalls => write!(f, "Too many nested calls"),
+            ParseError::TooDeeplyNested { offset } => write!(f, "Too deeply nested at {offset}"),
+            ParseError::DivisionByZero => write!(f, "Division by zero"),
+            ParseError::UndefinedResult => write!(f, "Undefined result"),
+            ParseError::OutOfRange => write!(f, "Result out of range"),
+            ParseError::NotAnInteger => write!(f, "Not an integer"),
+        }
+    }
+}
 
-    while let Some(char) = iter.nth(factor.length) {
-        match char {
-            'x' => {
-                factor = parse_factor(&input[result.length + 1..]);
+impl std::error::Error for ParseError {}
 
-                result.length += factor.length + 1;
-                result.value *= factor.value;
-            },
-            '/' => {
-                factor = parse_factor(&input[result.length + 1..]);
+/// Evaluates `input`, panicking on malformed expressions.
+/// Prefer `try_parse_expression` when the input comes from the user.
+pub fn parse_expression(input: &str) -> ParseResult {
+    try_parse_expression(input).unwrap_or_else(|err| panic!("{err}"))
+}
 
-                result.length += factor.length + 1;
-                result.value /= factor.value;
-            },
-            _ => break
-        }
-    }
+pub fn try_parse_expression(input: &str) -> Result<ParseResult, ParseError> {
+    try_parse_expression_with(input, &Context::default())
+}
+
+pub fn try_parse_expression_with<N: Number>(input: &str, context: &Context) -> Result<ParseResult<N>, ParseError> {
+    let expr = parse_in_context(input, NumberMode::default(), context)?;
 
-    result
+    Ok(ParseResult {
+        value: expr.evaluate(context)?
+    })
 }
 
-fn parse_factor(input: &str) -> ParseResult {
-    let first_char = input.chars().next().unwrap();
+/// Evaluates `input` like `try_parse_expression`, an assignment such as
+/// `rate=0.07` also storing its value in `context.variables`. A function
+/// definition goes to `context.functions` and has no value.
+pub fn evaluate_statement(input: &str, context: &mut Context) -> Result<Option<ParseResult>, ParseError> {
+    let (name, expr) = match parse_statement_in_context(input, NumberMode::default(), context)? {
+        Statement::Expression(expr) => (None, expr),
+        Statement::Assignment { name, value } => (Some(name), value),
+        Statement::Definition { name, function } => {
+            context.functions.insert(name, function);
+            return Ok(None);
+        },
+    };
+    let value: Decimal = expr.evaluate(context)?;
 
-    match first_char {
-        char if char.is_digit(10) => read_number(input),
-        '(' => {
-            let closing_par_index = find_closing_parenthese(input);
-            let sub_expr = &input[1..closing_par_index];
-            let mut result = parse_expression(sub_expr);
+    if let Some(name) = name {
+        context.variables.insert(name, Expr::Number(value.clone()));
+    }
+    Ok(Some(ParseResult { value }))
+}
 
-            result.length = closing_par_index + 1;
-            result
+/// Evaluates `input` with the backend `mode` selects and prints the result.
+pub fn evaluate_in_mode(input: &str, mode: NumberMode, context: &Context) -> Result<String, ParseError> {
+    let expr = parse_in_context(input, mode, context)?;
+
+    match mode {
+        NumberMode::Float => expr.evaluate::<f64>(context).map(|value| value.to_string()),
+        NumberMode::Decimal => expr.evaluate::<Decimal>(context).map(|value| value.to_string()),
+        NumberMode::Rational => expr.evaluate::<BigRational>(context).map(|value| value.to_string()),
+        NumberMode::Integer => expr.evaluate::<BigInt>(context).map(|value| value.to_string()),
+        NumberMode::Programmer => {
+            expr.evaluate::<BigInt>(&Context { wrapping: true, ..context.clone() }).map(|value| value.to_string())
         },
-        _ => unreachable!(),
     }
 }
 
+/// Evaluates `input` in programmer mode, wrapping around to `context.word`.
+/// Also tells whether it overflowed, the exact result being different.
+pub fn evaluate_in_word(input: &str, context: &Context) -> Result<(BigInt, bool), ParseError> {
+    let expr = parse_in_context(input, NumberMode::Programmer, context)?;
+    let value = expr.evaluate::<BigInt>(&Context { wrapping: true, ..context.clone() })?;
+    let exact = expr.evaluate::<BigInt>(&Context { wrapping: false, ..context.clone() });
 
-fn read_number(input: &str) -> ParseResult {
-    let ops = ['+', '-', 'x', '/', '(', ')'];
-    let length = input.find(&ops).unwrap_or(input.len());
-    let value: f64 = input.split_once(&ops)
-        .unwrap_or((&input, &""))
-        .0
-        .parse()
-        .unwrap();
+    Ok((value.clone(), exact.map_or(true, |exact| exact != value)))
+}
 
-    ParseResult {
-        length,
-        value
-    }
+/// Tokenizes and parses `input` into an `Expr` without evaluating it.
+pub fn parse(input: &str) -> Result<Expr, ParseError> {
+    parse_in_mode(input, NumberMode::default())
 }
 
-fn find_closing_parenthese(input: &str) -> usize {
-    let mut opened_parenthese_count: usize = 0;
-    let mut closed_parenthese_count: usize = 0;
+/// Like `parse`, reading the number literals `mode` allows, such as `0x1F`
+/// in programmer mode.
+pub fn parse_in_mode(input: &str, mode: NumberMode) -> Result<Expr, ParseError> {
+    parse_in_context(input, mode, &Context::default())
+}
+
+/// Like `parse_in_mode`, knowing the variables and functions of `context`,
+/// so that an `x` in `ratexbase` multiplies them.
+pub fn parse_in_context(input: &str, mode: NumberMode, context: &Context) -> Result<Expr, ParseError> {
+    let mut parser = Parser::new(input, mode, context)?;
+    let expr = parser.parse_expression()?;
+
+    parser.expect_end()?;
+    Ok(expr)
+}
+
+/// Like `parse_in_mode`, also reading `name=expression`, which assigns a
+/// variable, and `name(a,b)=expression`, which defines a function.
+pub fn parse_statement_in_mode(input: &str, mode: NumberMode) -> Result<Statement, ParseError> {
+    parse_statement_in_context(input, mode, &Context::default())
+}
+
+/// Like `parse_statement_in_mode`, knowing the names of `context` as
+/// `parse_in_context` does.
+pub fn parse_statement_in_context(input: &str, mode: NumberMode, context: &Context) -> Result<Statement, ParseError> {
+    let mut parser = Parser::new(input, mode, context)?;
+    let statement = parser.parse_statement()?;
+
+    parser.expect_end()?;
+    Ok(statement)
+}
+
+/// Factors nested deeper than this fail to parse, which keeps parentheses
+/// such as `((((1))))` from overflowing the stack, however many they are.
+pub const MAX_NESTING_DEPTH: usize = 100;
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+    /// Factors being parsed one inside the other.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str, mode: NumberMode, context: &Context) -> Result<Parser<'a>, ParseError> {
+        if input.is_empty() {
+            return Err(ParseError::EmptyInput);
+        }
+
+        Ok(Parser {
+            input,
+            tokens: tokenize_with(input, mode.reads_radix_prefixes(), &|name| context.defines(name))?,
+            position: 0,
+            depth: 0,
+        })
+    }
+
+    fn expect_end(&mut self) -> Result<(), ParseError> {
+        match self.next() {
+            None => Ok(()),
+            Some(Token { kind: TokenKind::CloseParenthese, offset }) => Err(ParseError::UnbalancedParentheses { offset }),
+            Some(token) => Err(self.unexpected(token)),
+        }
+    }
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+
+        self.position += 1;
+        token
+    }
+
+    /// A name directly followed by `=` is assigned the rest of the input.
+    fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        if let Some(definition) = self.parse_definition()? {
+            return Ok(definition);
+        }
+
+        let Some([Token { kind: TokenKind::Identifier(name), offset }, Token { kind: TokenKind::Assign, .. }]) = self.tokens.get(..2) else {
+            return Ok(Statement::Expression(self.parse_expression()?));
+        };
+        if is_reserved_name(name) {
+            return Err(ParseError::ReservedName { name: name.clone(), offset: *offset });
+        }
+        let name = name.clone();
+
+        self.position += 2;
+        Ok(Statement::Assignment { name, value: self.parse_expression()? })
+    }
+
+    /// `name(a,b)=body`, read only when the input starts with a name, then
+    /// names between commas in parentheses, then `=`.
+    fn parse_definition(&mut self) -> Result<Option<Statement>, ParseError> {
+        let Some([Token { kind: TokenKind::Identifier(name), offset }, Token { kind: TokenKind::OpenParenthese, .. }]) = self.tokens.get(..2) else {
+            return Ok(None);
+        };
+        let Some(close) = self.tokens.iter().position(|token| token.kind == TokenKind::CloseParenthese) else {
+            return Ok(None);
+        };
+        if self.tokens.get(close + 1).map(|token| &token.kind) != Some(&TokenKind::Assign) {
+            return Ok(None);
+        }
+
+        let mut parameters: Vec<String> = vec![];
 
-    for (index, char) in input.chars().enumerate() {
-        match char {
-            '(' => opened_parenthese_count += 1,
-            ')' => closed_parenthese_count += 1,
-            _ => (),
+        for (index, token) in self.tokens[2..close].iter().enumerate() {
+            match (&token.kind, index % 2) {
+                (TokenKind::Identifier(parameter), 0) => {
+                    if is_reserved_name(parameter) {
+                        return Err(ParseError::ReservedName { name: parameter.clone(), offset: token.offset });
+                    }
+                    if parameters.contains(parameter) {
+                        return Err(ParseError::DuplicateParameter { name: parameter.clone(), offset: token.offset });
+                    }
+                    parameters.push(parameter.clone());
+                },
+                (TokenKind::Comma, 1) => (),
+                _ => return Ok(None),
+            }
+        }
+        // A trailing comma
+        if close > 2 && self.tokens[close - 1].kind == TokenKind::Comma {
+            return Ok(None);
         }
-        if opened_parenthese_count == closed_parenthese_count {
-            return index;
+        if is_reserved_name(name) {
+            return Err(ParseError::ReservedName { name: name.clone(), offset: *offset });
+        }
+        let name = name.clone();
+
+        self.position = close + 2;
+        let body = self.parse_expression()?;
+
+        Ok(Some(Statement::Definition { name, function: UserFunction { parameters, body } }))
+    }
+
+    fn parse_expression(&mut self) -> Result<Expr, ParseError> {
+        self.parse_bitwise(BinaryOperator::Or.precedence())
+    }
+
+    /// Bitwise operators have one level each, from `|` to the shifts, all
+    /// looser than `+` and `-`. Operators of `min_precedence` or tighter
+    /// are read here, left-associative.
+    fn parse_bitwise(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_sum()?;
+
+        loop {
+            let operator = match self.peek() {
+                Some(TokenKind::And) => BinaryOperator::And,
+                Some(TokenKind::Or) => BinaryOperator::Or,
+                Some(TokenKind::Xor) => BinaryOperator::Xor,
+                Some(TokenKind::ShiftLeft) => BinaryOperator::ShiftLeft,
+                Some(TokenKind::ShiftRight) => BinaryOperator::ShiftRight,
+                Some(TokenKind::RotateLeft) => BinaryOperator::RotateLeft,
+                Some(TokenKind::RotateRight) => BinaryOperator::RotateRight,
+                _ => break
+            };
+            if operator.precedence() < min_precedence {
+                break;
+            }
+
+            self.position += 1;
+            expr = Expr::binary(operator, expr, self.parse_bitwise(operator.precedence() + 1)?);
         }
+
+        Ok(expr)
+    }
+
+    fn parse_sum(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_term()?;
+
+        loop {
+            let operator = match self.peek() {
+                Some(TokenKind::Plus) => BinaryOperator::Add,
+                Some(TokenKind::Minus) => BinaryOperator::Subtract,
+                _ => break
+            };
+
+            self.position += 1;
+            expr = Expr::binary(operator, expr, self.parse_term()?);
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_term(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_factor()?;
+
+        loop {
+            let operator = match self.peek() {
+                Some(TokenKind::Times) => BinaryOperator::Multiply,
+                Some(TokenKind::Divide) => BinaryOperator::Divide,
+                _ => break
+            };
+
+            self.position += 1;
+            expr = Expr::binary(operator, expr, self.parse_factor()?);
+        }
+
+        Ok(expr)
+    }
+
+    /// Every nested expression goes through here, where its depth is
+    /// checked.
+    fn parse_factor(&mut self) -> Result<Expr, ParseError> {
+        if self.depth == MAX_NESTING_DEPTH {
+            let offset = self.tokens.get(self.position).map_or(self.input.len(), |token| token.offset);
+
+            return Err(ParseError::TooDeeplyNested { offset });
+        }
+
+        self.depth += 1;
+        let expr = self.parse_signed();
+        self.depth -= 1;
+
+        expr
+    }
+
+    fn parse_signed(&mut self) -> Result<Expr, ParseError> {
+        let operator = match self.peek() {
+            Some(TokenKind::Plus) => UnaryOperator::Plus,
+            Some(TokenKind::Minus) => UnaryOperator::Negate,
+            Some(TokenKind::Root) => UnaryOperator::SquareRoot,
+            Some(TokenKind::Not) => UnaryOperator::Not,
+            _ => return self.parse_power()
+        };
+
+        self.position += 1;
+        Ok(Expr::unary(operator, self.parse_factor()?))
+    }
+
+    // The exponent is parsed as a whole factor, which makes `^` right-associative
+    fn parse_power(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.parse_percent()?;
+        let operator = match self.peek() {
+            Some(TokenKind::Power) => BinaryOperator::Power,
+            Some(TokenKind::Root) => BinaryOperator::Root,
+            _ => return Ok(expr)
+        };
+
+        self.position += 1;
+        Ok(Expr::binary(operator, expr, self.parse_factor()?))
+    }
+
+    fn parse_percent(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.parse_primary()?;
+
+        while self.peek() == Some(&TokenKind::Percent) {
+            self.position += 1;
+            expr = Expr::percent(expr);
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, ParseError> {
+        match self.next() {
+            Some(Token { kind: TokenKind::Number(value), .. }) => self.parse_fraction(Expr::Number(value)),
+            Some(Token { kind: TokenKind::OpenParenthese, offset }) => {
+                let expr = self.parse_expression()?;
+
+                match self.next() {
+                    Some(Token { kind: TokenKind::CloseParenthese, .. }) => Ok(expr),
+                    None => Err(ParseError::UnbalancedParentheses { offset }),
+                    Some(token) => Err(self.unexpected(token)),
+                }
+            },
+            Some(Token { kind: TokenKind::Identifier(name), .. }) => {
+                if name == ANSWER {
+                    return Ok(Expr::Answer);
+                }
+                if let Some(constant) = Constant::from_name(&name) {
+                    return Ok(Expr::Constant(constant));
+                }
+                let Some(function) = Function::from_name(&name) else {
+                    // Any other name is a variable, unless called like a user function
+                    if self.peek() == Some(&TokenKind::OpenParenthese) {
+                        return Ok(Expr::UserCall { name, arguments: self.parse_arguments()? });
+                    }
+                    return Ok(Expr::Variable(name));
+                };
+
+                match self.peek() {
+                    Some(TokenKind::OpenParenthese) => Ok(Expr::call(function, self.parse_primary()?)),
+                    Some(_) => {
+                        let token = self.next().unwrap();
+                        Err(self.unexpected(token))
+                    },
+                    None => Err(ParseError::UnexpectedEnd { offset: self.input.len() }),
+                }
+            },
+            Some(token) => Err(self.unexpected(token)),
+            None => Err(ParseError::UnexpectedEnd { offset: self.input.len() }),
+        }
+    }
+
+    /// Expressions between commas in parentheses, maybe none.
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let Some(Token { offset, .. }) = self.next() else {
+            return Err(ParseError::UnexpectedEnd { offset: self.input.len() });
+        };
+        let mut arguments: Vec<Expr> = vec![];
+
+        if self.peek() == Some(&TokenKind::CloseParenthese) {
+            self.position += 1;
+            return Ok(arguments);
+        }
+
+        loop {
+            arguments.push(self.parse_expression()?);
+
+            match self.next() {
+                Some(Token { kind: TokenKind::Comma, .. }) => (),
+                Some(Token { kind: TokenKind::CloseParenthese, .. }) => return Ok(arguments),
+                None => return Err(ParseError::UnbalancedParentheses { offset }),
+                Some(token) => return Err(self.unexpected(token)),
+            }
+        }
+    }
+
+    /// `a⌟b` is `a/b` and `a⌟b⌟c` is `a+b/c`, both kept together like a
+    /// single number, so that `1⌟2^2` is a quarter.
+    fn parse_fraction(&mut self, whole: Expr) -> Result<Expr, ParseError> {
+        if self.peek() != Some(&TokenKind::FractionBar) {
+            return Ok(whole);
+        }
+        self.position += 1;
+        let numerator = self.parse_number()?;
+
+        if self.peek() != Some(&TokenKind::FractionBar) {
+            return Ok(Expr::binary(BinaryOperator::Divide, whole, numerator));
+        }
+        self.position += 1;
+        let denominator = self.parse_number()?;
+
+        Ok(Expr::binary(
+            BinaryOperator::Add,
+            whole,
+            Expr::binary(BinaryOperator::Divide, numerator, denominator),
+        ))
+    }
+
+    fn parse_number(&mut self) -> Result<Expr, ParseError> {
+        match self.next() {
+            Some(Token { kind: TokenKind::Number(value), .. }) => Ok(Expr::Number(value)),
+            Some(token) => Err(self.unexpected(token)),
+            None => Err(ParseError::UnexpectedEnd { offset: self.input.len() }),
+        }
+    }
+
+    fn unexpected(&self, token: Token) -> ParseError {
+        let symbol = self.input[token.offset..].chars().next().unwrap_or_default();
+
+        ParseError::UnexpectedToken { token: symbol, offset: token.offset }
     }
-    panic!("Error while parsing parentheses");
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::operations::parse_expression;
+    use crate::ast::{AngleMode, Context};
+    use crate::decimal::Decimal;
+    use crate::number::NumberMode;
+    use crate::number::Word;
+    use crate::ast::{Expr, Statement};
+    use crate::operations::{evaluate_in_mode, evaluate_in_word, evaluate_statement, parse, parse_expression, parse_statement_in_mode, try_parse_expression, try_parse_expression_with, ParseError};
 
     #[test]
     fn parse_expression_tests() {
-        let data: Vec<(&str, f64)> = vec![
-            ("1+1", 2.0),
-            ("1-1", 0.0),
-            ("2x(3-2+(2/2))", 4.0),
-            ("10/2", 5.0),
-            ("1.5+2.3", 3.8),
-            ("2.5x4", 10.0),
-            ("100/4", 25.0),
-            ("2.5-0.5", 2.0),
-            ("1.1+2.2-3.0", 0.3),
-            ("(2+3)x(1.5+0.5)", 10.0),
-            ("3.3/1.1", 3.0),
-            ("(2.2+3.3)x2", 11.0),
-            ("2x(2.1+2.9/1.45)", 8.2),
-            ("0.1+0.2", 0.3),
-            ("5-(2x(1.25))", 2.5),
-            ("(5-1)x(2+2)", 16.0),
-            ("(2.5x4)/(1+1)", 5.0),
-            ("1.2+2.3", 3.5),
-            ("5x3", 15.0),
-            ("10-5", 5.0),
-            ("50/2", 25.0),
-            ("(5+5)x2", 20.0),
-            ("2x(3+3)", 12.0),
-            ("(1.5+2.5)x4", 16.0),
-            ("(12/4)x(2+1)", 9.0),
-            ("3.5x(2+3)x2", 35.0),
-            ("(4.5+1.5)x(2x3)", 36.0),
-            ("(2.5x(2+3))/(1+1)", 6.25),
-            ("2x(2+(3x2))/2", 8.0),
-            ("((2+3)x2)x2", 20.0),
-            ("(2+(3x(2+1)))", 11.0),
-            ("(4/(2/1))+(3x2)", 8.0),
-            ("((1.2+1.3)x2)-1", 4.0),
-            ("1.1+2.2", 3.3),
-            ("(1.5x4.2)+(3.1/2.0)", 7.85),
-            ("(0.1+0.2)+0.3", 0.6),
-            ("(10/3)x3", 10.0),
-            ("2.5x3.2x(1+2)", 24.0),
-            ("10x(2.5+0.5)-(3x2)", 24.0),
-            ("(2.2+3.3)x(2x2.5)", 27.5),
-            ("(4.5-1.5)/(1.5x0.5)", 4.0)
+        let data: Vec<(&str, &str)> = vec![
+            ("1+1", "2"),
+            ("1-1", "0"),
+            ("2x(3-2+(2/2))", "4"),
+            ("10/2", "5"),
+            ("1.5+2.3", "3.8"),
+            ("2.5x4", "10"),
+            ("100/4", "25"),
+            ("2.5-0.5", "2"),
+            ("1.1+2.2-3.0", "0.3"),
+            ("(2+3)x(1.5+0.5)", "10"),
+            ("3.3/1.1", "3"),
+            ("(2.2+3.3)x2", "11"),
+            ("2x(2.1+2.9/1.45)", "8.2"),
+            ("0.1+0.2", "0.3"),
+            ("5-(2x(1.25))", "2.5"),
+            ("(5-1)x(2+2)", "16"),
+            ("(2.5x4)/(1+1)", "5"),
+            ("1.2+2.3", "3.5"),
+            ("5x3", "15"),
+            ("10-5", "5"),
+            ("50/2", "25"),
+            ("(5+5)x2", "20"),
+            ("2x(3+3)", "12"),
+            ("(1.5+2.5)x4", "16"),
+            ("(12/4)x(2+1)", "9"),
+            ("3.5x(2+3)x2", "35"),
+            ("(4.5+1.5)x(2x3)", "36"),
+            ("(2.5x(2+3))/(1+1)", "6.25"),
+            ("2x(2+(3x2))/2", "8"),
+            ("((2+3)x2)x2", "20"),
+            ("(2+(3x(2+1)))", "11"),
+            ("(4/(2/1))+(3x2)", "8"),
+            ("((1.2+1.3)x2)-1", "4"),
+            ("1.1+2.2", "3.3"),
+            ("(1.5x4.2)+(3.1/2.0)", "7.85"),
+            ("(0.1+0.2)+0.3", "0.6"),
+            ("(10/3)x3", "10"),
+            ("2.5x3.2x(1+2)", "24"),
+            ("10x(2.5+0.5)-(3x2)", "24"),
+            ("(2.2+3.3)x(2x2.5)", "27.5"),
+            ("(4.5-1.5)/(1.5x0.5)", "4"),
+            ("-5+3", "-2"),
+            ("2x-3", "-6"),
+            ("-(2+3)x2", "-10"),
+            ("4/-2", "-2"),
+            ("(-1.5)x(-2)", "3"),
+            ("1--1", "2"),
+            ("+2-+1", "1"),
+            ("--2", "2"),
+            ("2^10", "1024"),
+            ("2^3^2", "512"),
+            ("(2^3)^2", "64"),
+            ("-2^2", "-4"),
+            ("(-2)^2", "4"),
+            ("2^-1", "0.5"),
+            ("3x2^2", "12"),
+            ("√16", "4"),
+            ("√(9+16)", "5"),
+            ("2x√9", "6"),
+            ("3√27", "3"),
+            ("3√-8", "-2"),
+            ("4√16+1", "3"),
+            ("√√16", "2"),
+            ("sin(30)", "0.5"),
+            ("cos(60)+1", "1.5"),
+            ("tan(45)x2", "2"),
+            ("ln(exp(2))", "2"),
+            ("log(1000)", "3"),
+            ("abs(-2.5)", "2.5"),
+            ("sqrt(16)+sqrt(9)", "7"),
+            ("2xsin(90)^2", "2"),
+            ("-cos(0)", "-1"),
+            ("2xπ", "6.283185307179586476925286766559"),
+            ("pi/π", "1"),
+            ("ln(e^2)", "2"),
+            ("exe", "7.389056098930650227230427460575")
         ];
-        
+
         for (input, expected_result) in data {
             let result = parse_expression(input).value;
-            assert!((result - expected_result).abs() < 1e-6, "Failed test for input {input}: got {result} but expected {expected_result}");
+            assert_eq!(result.to_string(), expected_result, "Failed test for input {input}");
+        }
+    }
+
+    #[test]
+    fn percent_tests() {
+        let data: Vec<(&str, &str)> = vec![
+            ("50%", "0.5"),
+            ("200+10%", "220"),
+            ("200-10%", "180"),
+            ("200x10%", "20"),
+            ("200/10%", "2000"),
+            ("200+-10%", "180"),
+            ("(100+100)+10%", "220"),
+            ("200+10%x2", "200.2"),
+            ("200+10%+10%", "242"),
+            ("10%^2", "0.01"),
+            ("(50+50)%", "1"),
+            ("-50%", "-0.5"),
+            ("200+(10)%", "220"),
+            ("200+ans%", "200"),
+            ("10%x200", "20"),
+            ("10%x10%", "0.01"),
+        ];
+
+        for (input, expected_result) in data {
+            let result = parse_expression(input).value;
+            assert_eq!(result.to_string(), expected_result, "Failed test for input {input}");
+        }
+    }
+
+    #[test]
+    fn percent_integer_modes_tests() {
+        let data: Vec<(&str, NumberMode, Result<&str, ParseError>)> = vec![
+            ("200+10%", NumberMode::Integer, Ok("220")),
+            ("200-10%", NumberMode::Integer, Ok("180")),
+            ("200x10%", NumberMode::Integer, Ok("20")),
+            ("10%x200", NumberMode::Integer, Ok("20")),
+            ("200/10%", NumberMode::Integer, Ok("2000")),
+            ("300%", NumberMode::Integer, Ok("3")),
+            ("50%", NumberMode::Integer, Err(ParseError::NotAnInteger)),
+            ("205+10%", NumberMode::Integer, Err(ParseError::NotAnInteger)),
+            ("200+10%", NumberMode::Programmer, Ok("220")),
+            ("0xC8x10%", NumberMode::Programmer, Ok("20")),
+            ("200/10%", NumberMode::Programmer, Ok("2000")),
+            ("200+-10%", NumberMode::Programmer, Ok("180")),
+            ("50%", NumberMode::Programmer, Err(ParseError::NotAnInteger)),
+        ];
+
+        for (input, mode, expected_result) in data {
+            let result = evaluate_in_mode(input, mode, &Context::default());
+            assert_eq!(result, expected_result.map(String::from), "Failed test for input {input} in {mode:?}");
+        }
+    }
+
+    #[test]
+    fn angle_mode_tests() {
+        let data: Vec<(&str, AngleMode, &str)> = vec![
+            ("sin(90)", AngleMode::Degrees, "1"),
+            ("sin(100)", AngleMode::Gradians, "1"),
+            ("cos(3.141592653589793)", AngleMode::Radians, "-1"),
+            ("tan(50)", AngleMode::Gradians, "1"),
+            ("ln(1)", AngleMode::Radians, "0"),
+            ("sin(180)", AngleMode::Degrees, "0"),
+        ];
+
+        for (input, angle_mode, expected_result) in data {
+            let context = Context { angle_mode, ..Context::default() };
+            let result = try_parse_expression_with::<Decimal>(input, &context).unwrap().value;
+            assert_eq!(result.to_string(), expected_result, "Failed test for input {input} in {angle_mode}");
+        }
+
+        let undefined: Vec<(&str, AngleMode)> = vec![
+            ("tan(90)", AngleMode::Degrees),
+            ("tan(-270)", AngleMode::Degrees),
+            ("tan(100)", AngleMode::Gradians),
+            ("tan(π/2)", AngleMode::Radians),
+        ];
+
+        for (input, angle_mode) in undefined {
+            let context = Context { angle_mode, ..Context::default() };
+            let result = try_parse_expression_with::<Decimal>(input, &context);
+            assert_eq!(result.unwrap_err(), ParseError::UndefinedResult, "Failed test for input {input} in {angle_mode}");
+        }
+    }
+
+    #[test]
+    fn precision_tests() {
+        let data: Vec<(&str, u64, &str)> = vec![
+            ("1/3", 5, "0.33333"),
+            ("2/3", 5, "0.66667"),
+            ("1/3x3", 5, "1"),
+            ("√2", 10, "1.414213562"),
+            ("1/7", 32, "0.14285714285714285714285714285714"),
+            ("123456", 3, "123000"),
+        ];
+
+        for (input, precision, expected_result) in data {
+            let context = Context { precision, ..Context::default() };
+            let result = try_parse_expression_with::<Decimal>(input, &context).unwrap().value;
+            assert_eq!(result.to_string(), expected_result, "Failed test for input {input} at precision {precision}");
+        }
+    }
+
+    #[test]
+    fn answer_tests() {
+        let data: Vec<(&str, &str, NumberMode, &str)> = vec![
+            ("ans", "0", NumberMode::Decimal, "0"),
+            ("ansx2", "4.5", NumberMode::Decimal, "9"),
+            ("√ans+1", "16", NumberMode::Decimal, "5"),
+            ("ans^2", "-3", NumberMode::Decimal, "9"),
+            ("ansx3", "1⌟3", NumberMode::Rational, "1"),
+            ("ans+ans", "3⌟1⌟2", NumberMode::Rational, "7"),
+            ("ans", "7⌟2", NumberMode::Integer, "3"),
+        ];
+
+        for (input, answer, mode, expected_result) in data {
+            let context = Context { answer: parse(answer).unwrap(), ..Context::default() };
+            let result = evaluate_in_mode(input, mode, &context).unwrap();
+            assert_eq!(result, expected_result, "Failed test for input {input} with ans = {answer}");
         }
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn number_mode_tests() {
+        let data: Vec<(&str, NumberMode, Result<&str, ParseError>)> = vec![
+            ("0.1+0.2", NumberMode::Float, Ok("0.30000000000000004")),
+            ("0.1+0.2", NumberMode::Decimal, Ok("0.3")),
+            ("0.1+0.2", NumberMode::Rational, Ok("3/10")),
+            ("1/3+1/6", NumberMode::Rational, Ok("1/2")),
+            ("(1/3)x3", NumberMode::Rational, Ok("1")),
+            ("2^-2", NumberMode::Rational, Ok("1/4")),
+            ("√(9/4)", NumberMode::Rational, Ok("3/2")),
+            ("1⌟2^2", NumberMode::Rational, Ok("1/4")),
+            ("-3⌟1⌟2x2", NumberMode::Rational, Ok("-7")),
+            ("3⌟1⌟2", NumberMode::Decimal, Ok("3.5")),
+            ("2^100", NumberMode::Integer, Ok("1267650600228229401496703205376")),
+            ("7/2", NumberMode::Integer, Ok("3")),
+            ("-7/2", NumberMode::Integer, Ok("-3")),
+            ("√10", NumberMode::Integer, Ok("3")),
+            ("2^-1", NumberMode::Integer, Ok("0")),
+            ("1.5x2", NumberMode::Integer, Err(ParseError::NotAnInteger)),
+            ("1/0", NumberMode::Float, Err(ParseError::DivisionByZero)),
+            ("1/0", NumberMode::Rational, Err(ParseError::DivisionByZero)),
+            ("1/0", NumberMode::Integer, Err(ParseError::DivisionByZero)),
+            ("10^20000", NumberMode::Integer, Err(ParseError::OutOfRange)),
+            ("10^400", NumberMode::Float, Err(ParseError::OutOfRange)),
+            ("10^200x10^200", NumberMode::Float, Err(ParseError::OutOfRange)),
+            ("√-1", NumberMode::Float, Err(ParseError::UndefinedResult)),
+            ("0x1F+0b1", NumberMode::Programmer, Ok("32")),
+            ("0o17x2-20", NumberMode::Programmer, Ok("10")),
+            ("0xFF/0x10", NumberMode::Programmer, Ok("15")),
+            ("0x1F", NumberMode::Integer, Err(ParseError::UnexpectedToken { token: 'F', offset: 3 })),
+        ];
+
+        for (input, mode, expected_result) in data {
+            let result = evaluate_in_mode(input, mode, &Context::default());
+            assert_eq!(result, expected_result.map(String::from), "Failed test for input {input} in {mode:?}");
+        }
+
+        // Only decimals are kept within 10^100
+        let context = Context::default();
+        assert_eq!(evaluate_in_mode("10^101", NumberMode::Integer, &context), Ok(format!("1{}", "0".repeat(101))));
+        assert_eq!(evaluate_in_mode("10^300/10^299", NumberMode::Float, &context), Ok(String::from("10")));
+        assert_eq!(evaluate_in_mode("10^101", NumberMode::Decimal, &context), Err(ParseError::OutOfRange));
+    }
+
+    #[test]
+    fn bitwise_tests() {
+        let data: Vec<(&str, &str, Result<&str, ParseError>)> = vec![
+            ("0xF0&0x3C", "i64", Ok("48")),
+            ("0xF0|0x0F", "i64", Ok("255")),
+            ("0xFF⊻0x0F", "i64", Ok("240")),
+            ("6&3|8", "i64", Ok("10")),
+            ("1|2&3", "i64", Ok("3")),
+            ("1+1«2", "i64", Ok("8")),
+            ("~0", "u8", Ok("255")),
+            ("~0", "i8", Ok("-1")),
+            ("0x80«1", "u8", Ok("0")),
+            ("-16»2", "i8", Ok("-4")),
+            ("0x81↺1", "u8", Ok("3")),
+            ("0x81↻1", "u8", Ok("192")),
+            ("1↺33", "u32", Ok("2")),
+            ("127+1", "i8", Ok("-128")),
+            ("200+100", "u8", Ok("44")),
+            ("0-1", "u16", Ok("65535")),
+            ("1«-1", "i64", Err(ParseError::OutOfRange)),
+            ("2^333", "u64", Ok("0")),
+            ("3^300", "u64", Ok("4157753088978724465")),
+            ("3^400", "i8", Ok("65")),
+            ("2^7", "i8", Ok("-128")),
+            ("7^5", "u64", Ok("16807")),
+            ("2^-1", "i8", Ok("0")),
+        ];
+
+        for (input, word, expected_result) in data {
+            let context = Context { word: Word::from_name(word).unwrap(), ..Context::default() };
+            let result = evaluate_in_mode(input, NumberMode::Programmer, &context);
+
+            assert_eq!(result, expected_result.map(String::from), "Failed test for input {input} in {word}");
+        }
+
+        assert_eq!(evaluate_in_mode("5&3", NumberMode::Decimal, &Context::default()), Ok(String::from("1")));
+        assert_eq!(evaluate_in_mode("(1.5x2)&1", NumberMode::Decimal, &Context::default()), Ok(String::from("1")));
+        assert_eq!(evaluate_in_mode("(0.5+0.5)«1", NumberMode::Decimal, &Context::default()), Ok(String::from("2")));
+        assert_eq!(evaluate_in_mode("(2.5x4)|(0.25x4)", NumberMode::Decimal, &Context::default()), Ok(String::from("11")));
+        assert_eq!(evaluate_in_mode("1.5&1", NumberMode::Decimal, &Context::default()), Err(ParseError::NotAnInteger));
+        assert_eq!(evaluate_in_mode("1«63", NumberMode::Decimal, &Context::default()), Err(ParseError::OutOfRange));
+    }
+
+    #[test]
+    fn overflow_tests() {
+        let data: Vec<(&str, &str, bool)> = vec![
+            ("100+27", "i8", false),
+            ("100+28", "i8", true),
+            ("0-1", "u8", true),
+            ("0xFF", "i8", true),
+            ("~0", "u8", false),
+            ("(200+100)/2", "u8", true),
+            ("0x80«1", "u8", true),
+            ("1«8", "i8", true),
+            ("1«6", "i8", false),
+            ("(1«8)»8", "i8", true),
+            ("2^333", "u64", true),
+            ("3^400", "i8", true),
+            ("2^6", "i8", false),
+        ];
+
+        for (input, word, expected_overflow) in data {
+            let context = Context { word: Word::from_name(word).unwrap(), ..Context::default() };
+            let (_, overflowed) = evaluate_in_word(input, &context).unwrap();
+
+            assert_eq!(overflowed, expected_overflow, "Failed test for input {input} in {word}");
+        }
+    }
+
+    #[test]
+    fn parse_display_tests() {
+        let data: Vec<(&str, &str)> = vec![
+            ("1+1", "1+1"),
+            ("2x(3-2+(2/2))", "2x(3-2+2/2)"),
+            ("(2+3)x(1.5+0.5)", "(2+3)x(1.5+0.5)"),
+            ("((2+3)x2)x2", "(2+3)x2x2"),
+            ("1-(2-3)", "1-(2-3)"),
+            ("(1-2)-3", "1-2-3"),
+            ("10/(4/2)", "10/(4/2)"),
+            ("(.5)", "0.5"),
+            ("-5+3", "-5+3"),
+            ("2x-(3+1)", "2x-(3+1)"),
+            ("1--1", "1--1"),
+            ("2^3^2", "2^3^2"),
+            ("(2^3)^2", "(2^3)^2"),
+            ("-2^2", "-2^2"),
+            ("(-2)^2", "(-2)^2"),
+            ("2^-1", "2^(-1)"),
+            ("√(9+16)", "√(9+16)"),
+            ("3√27x2", "3√27x2"),
+            ("2xsin(30+1)", "2xsin(30+1)"),
+            ("-ln((2))", "-ln(2)"),
+            ("2xpi", "2xπ"),
+            ("7⌟2", "7/2"),
+            ("3⌟1⌟2^2", "(3+1/2)^2"),
+            ("-7⌟2", "-(7/2)"),
+            ("ansx2", "ansx2"),
+            ("200+10%", "200+10%"),
+            ("(1+2)%", "(1+2)%"),
+            ("10%^2", "10%^2"),
+            ("(-10)%", "(-10)%"),
+            ("1|2&3", "1|2&3"),
+            ("(1|2)&3", "(1|2)&3"),
+            ("1+2«3", "1+2«3"),
+            ("(1«2)+3", "(1«2)+3"),
+            ("~(5&3)", "~(5&3)"),
+            ("~-5", "~-5"),
+            ("2x(rate)", "2xrate"),
+            ("f(x,2x(3))+xx2", "f(x,2x3)+xx2"),
+            ("g()", "g()"),
+        ];
+
+        for (input, expected_output) in data {
+            let expr = parse(input).unwrap();
+            let printed = expr.to_string();
+
+            assert_eq!(printed, expected_output, "Failed test for input {input}");
+            assert_eq!(parse(&printed).unwrap(), expr, "Round trip changed the tree of {input}");
+        }
+    }
+
+    #[test]
+    fn try_parse_expression_errors_tests() {
+        let nested_parentheses = format!("{}1{}", "(".repeat(2000), ")".repeat(2000));
+        let nested_signs = format!("{}1", "-".repeat(2000));
+        let data: Vec<(&str, ParseError)> = vec![
+            ("", ParseError::EmptyInput),
+            ("1+", ParseError::UnexpectedEnd { offset: 2 }),
+            ("2x/3", ParseError::UnexpectedToken { token: '/', offset: 2 }),
+            ("2x-", ParseError::UnexpectedEnd { offset: 3 }),
+            ("1+#", ParseError::UnexpectedToken { token: '#', offset: 2 }),
+            ("1+a", ParseError::UndefinedVariable { name: String::from("a") }),
+            ("(1+2", ParseError::UnbalancedParentheses { offset: 0 }),
+            ("2x((1+2)", ParseError::UnbalancedParentheses { offset: 2 }),
+            ("1+2)", ParseError::UnbalancedParentheses { offset: 3 }),
+            ("()", ParseError::UnexpectedToken { token: ')', offset: 1 }),
+            ("1..2+1", ParseError::InvalidNumber { literal: String::from("1..2"), offset: 0 }),
+            ("3x.", ParseError::InvalidNumber { literal: String::from("."), offset: 2 }),
+            ("1é", ParseError::UnexpectedToken { token: 'é', offset: 1 }),
+            ("2xfoo(1)", ParseError::UndefinedFunction { name: String::from("foo") }),
+            ("rate=1", ParseError::UnexpectedToken { token: '=', offset: 4 }),
+            ("sin+1", ParseError::UnexpectedToken { token: '+', offset: 3 }),
+            ("2xsin", ParseError::UnexpectedEnd { offset: 5 }),
+            ("1⌟(2)", ParseError::UnexpectedToken { token: '(', offset: 4 }),
+            ("1⌟2⌟", ParseError::UnexpectedEnd { offset: 8 }),
+            ("⌟2", ParseError::UnexpectedToken { token: '⌟', offset: 0 }),
+            ("%5", ParseError::UnexpectedToken { token: '%', offset: 0 }),
+            ("2+%", ParseError::UnexpectedToken { token: '%', offset: 2 }),
+            ("5%5", ParseError::UnexpectedToken { token: '5', offset: 2 }),
+            ("1/0", ParseError::DivisionByZero),
+            ("0^-1", ParseError::DivisionByZero),
+            ("√-4", ParseError::UndefinedResult),
+            ("ln(0)", ParseError::UndefinedResult),
+            ("10^101", ParseError::OutOfRange),
+            (&nested_parentheses, ParseError::TooDeeplyNested { offset: 100 }),
+            (&nested_signs, ParseError::TooDeeplyNested { offset: 100 }),
+        ];
+
+        for (input, expected_error) in data {
+            let result = try_parse_expression(input);
+            assert_eq!(result.unwrap_err(), expected_error, "Failed test for input {input}");
+        }
+    }
+
+    #[test]
+    fn parse_statement_tests() {
+        let data: Vec<(&str, Result<Statement, ParseError>)> = vec![
+            ("rate=0.07", Ok(Statement::Assignment { name: String::from("rate"), value: parse("0.07").unwrap() })),
+            ("total=2xrate", Ok(Statement::Assignment { name: String::from("total"), value: parse("2xrate").unwrap() })),
+            ("1200xrate", Ok(Statement::Expression(parse("1200xrate").unwrap()))),
+            ("pi=3", Err(ParseError::ReservedName { name: String::from("pi"), offset: 0 })),
+            ("ans=1", Err(ParseError::ReservedName { name: String::from("ans"), offset: 0 })),
+            ("rate=", Err(ParseError::UnexpectedEnd { offset: 5 })),
+            ("a=b=1", Err(ParseError::UnexpectedToken { token: '=', offset: 3 })),
+            ("2=1", Err(ParseError::UnexpectedToken { token: '=', offset: 1 })),
+            ("=1", Err(ParseError::UnexpectedToken { token: '=', offset: 0 })),
+        ];
+
+        for (input, expected_statement) in data {
+            assert_eq!(parse_statement_in_mode(input, NumberMode::Decimal), expected_statement, "Failed test for input {input}");
+        }
+    }
+
+    fn evaluate_statements(inputs: Vec<(&str, Result<Option<&str>, ParseError>)>, context: &mut Context) {
+        for (input, expected_result) in inputs {
+            let result = evaluate_statement(input, context).map(|result| result.map(|result| result.value.to_string()));
+            assert_eq!(result.as_ref().map(Option::as_deref).map_err(Clone::clone), expected_result, "Failed test for input {input}");
+        }
+    }
+
+    #[test]
+    fn evaluate_statement_tests() {
+        let mut context = Context::default();
+
+        evaluate_statements(vec![
+            ("rate=0.07", Ok(Some("0.07"))),
+            ("1200xrate", Ok(Some("84"))),
+            ("base=1200", Ok(Some("1200"))),
+            ("rate=rate+0.01", Ok(Some("0.08"))),
+            ("basexrate", Ok(Some("96"))),
+            ("1+vat", Err(ParseError::UndefinedVariable { name: String::from("vat") })),
+            ("e=1", Err(ParseError::ReservedName { name: String::from("e"), offset: 0 })),
+        ], &mut context);
+        assert_eq!(context.variables.keys().collect::<Vec<_>>(), ["base", "rate"]);
+        assert_eq!(context.variables["rate"], Expr::Number("0.08".parse().unwrap()));
+    }
+
+    #[test]
+    fn user_functions_tests() {
+        let mut context = Context::default();
+
+        evaluate_statements(vec![
+            ("f(x,y)=x^2+y", Ok(None)),
+            ("f(3,4)", Ok(Some("13"))),
+            ("2xf(1,1)xx", Err(ParseError::UndefinedVariable { name: String::from("x") })),
+            ("x=10", Ok(Some("10"))),
+            ("f(x,-x)+x", Ok(Some("100"))),
+            ("g(a)=f(a,a)/2", Ok(None)),
+            ("g(4)", Ok(Some("10"))),
+            ("tip(total)=totalxrate", Ok(None)),
+            ("tip(100)", Err(ParseError::UndefinedVariable { name: String::from("rate") })),
+            ("rate=0.15", Ok(Some("0.15"))),
+            ("tip(100)", Ok(Some("15"))),
+            ("rate=0.2", Ok(Some("0.2"))),
+            ("tip(100)", Ok(Some("20"))),
+            ("one()=1", Ok(None)),
+            ("one()+1", Ok(Some("2"))),
+            ("f(1)", Err(ParseError::WrongArgumentCount { name: String::from("f"), expected: 2, found: 1 })),
+            ("h(2)", Err(ParseError::UndefinedFunction { name: String::from("h") })),
+            ("loop(n)=loop(n+1)", Ok(None)),
+            ("loop(0)", Err(ParseError::TooManyNestedCalls)),
+            ("f(x,x)=x", Err(ParseError::DuplicateParameter { name: String::from("x"), offset: 4 })),
+            ("f(pi)=1", Err(ParseError::ReservedName { name: String::from("pi"), offset: 2 })),
+            ("sin(x)=1", Err(ParseError::ReservedName { name: String::from("sin"), offset: 0 })),
+            ("f(x,)=1", Err(ParseError::UnexpectedToken { token: ')', offset: 4 })),
+            ("f(2)=1", Err(ParseError::UnexpectedToken { token: '=', offset: 4 })),
+            ("f(1,2", Err(ParseError::UnbalancedParentheses { offset: 1 })),
+        ], &mut context);
+        assert_eq!(context.functions.keys().collect::<Vec<_>>(), ["f", "g", "loop", "one", "tip"]);
+        assert_eq!(context.functions["f"].definition("f"), "f(x,y)=x^2+y");
+    }
+}
@@ -0,0 +1,116 @@
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::engine::{CalculatorEngine, MessageEmit};
+
+const PROMPT: &str = "> ";
+
+pub const HELP: &str = "Type an expression to evaluate it, `ans` being the previous result.
+`name = expression` also gives its result to the variable `name`,
+and `f(x, y) = expression` defines a function called as `f(3, 4)`.
+Up and down arrows go through the previous lines.
+
+  :clear  Forgets the history and clears the screen
+  :help   Prints this message
+  :quit   Leaves, as does Ctrl+D";
+
+/// What to do after a line.
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    Print(String),
+    Error(String),
+    Clear,
+    Quit,
+    Nothing,
+}
+
+/// The calculator in a terminal: lines go through the same engine as the
+/// keypad, so results are formatted as on the display.
+#[derive(Default)]
+pub struct Repl {
+    engine: CalculatorEngine,
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl { engine: CalculatorEngine::new() }
+    }
+
+    pub fn handle_line(&mut self, line: &str) -> Reply {
+        match line.trim() {
+            "" => Reply::Nothing,
+            ":quit" | ":q" => Reply::Quit,
+            ":help" => Reply::Print(HELP.to_string()),
+            ":clear" => {
+                self.engine.handle_message(MessageEmit::ClearHistory);
+                Reply::Clear
+            },
+            command if command.starts_with(':') => Reply::Error(format!("Unknown command '{command}', see :help")),
+            expression => match self.engine.evaluate_typed(expression) {
+                Ok(result) => Reply::Print(result),
+                Err(err) => Reply::Error(err),
+            },
+        }
+    }
+}
+
+/// Reads lines until `:quit` or the end of input. Ctrl+C drops the line
+/// being typed.
+pub fn run() -> rustyline::Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    let mut repl = Repl::new();
+
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        if !line.trim().is_empty() {
+            editor.add_history_entry(line.as_str())?;
+        }
+        match repl.handle_line(&line) {
+            Reply::Print(text) => println!("{text}"),
+            Reply::Error(text) => eprintln!("{text}"),
+            Reply::Clear => {
+                editor.clear_history()?;
+                editor.clear_screen()?;
+            },
+            Reply::Quit => return Ok(()),
+            Reply::Nothing => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Repl, Reply};
+
+    #[test]
+    fn handle_line_tests() {
+        let mut repl = Repl::new();
+        let data: Vec<(&str, Reply)> = vec![
+            ("1234 x 10", Reply::Print(String::from("12 340"))),
+            ("ans / 4", Reply::Print(String::from("3 085"))),
+            ("2 * ans", Reply::Print(String::from("6 170"))),
+            ("1/0", Reply::Error(String::from("Division by zero"))),
+            ("ans+1", Reply::Print(String::from("6 171"))),
+            ("rate = 0.5", Reply::Print(String::from("0.5"))),
+            ("ans x rate", Reply::Print(String::from("0.25"))),
+            ("f(x) = 2 x x + rate", Reply::Print(String::from("f(x) defined"))),
+            ("f(3)", Reply::Print(String::from("6.5"))),
+            ("2+", Reply::Error(String::from("Unexpected end at 2"))),
+            ("   ", Reply::Nothing),
+            (":clear", Reply::Clear),
+            (":nope", Reply::Error(String::from("Unknown command ':nope', see :help"))),
+            (" :quit ", Reply::Quit),
+        ];
+
+        for (line, expected_reply) in data {
+            assert_eq!(repl.handle_line(line), expected_reply, "Failed test for line {line:?}");
+        }
+        assert!(repl.engine.calculations().is_empty());
+    }
+}
@@ -0,0 +1,433 @@
+use std::fmt;
+
+use crate::ast::{AngleMode, Context};
+use crate::engine::{CalculatorEngine, MessageEmit};
+use crate::number::{NumberMode, Word, RADIX_PREFIXES};
+use crate::operations::{evaluate_in_mode, ParseError};
+
+/// Names of the stack levels shown, from the top of the display down to X.
+const LEVEL_NAMES: [&str; 3] = ["T", "Z", "Y"];
+
+#[derive(Debug, PartialEq)]
+pub enum RpnError {
+    /// An operator needs more levels than the stack has.
+    TooFewArguments,
+    Evaluation(ParseError),
+}
+
+impl fmt::Display for RpnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpnError::TooFewArguments => write!(f, "Too few arguments"),
+            RpnError::Evaluation(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<ParseError> for RpnError {
+    fn from(err: ParseError) -> RpnError {
+        RpnError::Evaluation(err)
+    }
+}
+
+/// Input logic of the RPN mode, fed the same `MessageEmit` values as
+/// `CalculatorEngine`: `=` is ENTER and operators take their operands from
+/// the stack. Values are kept as the evaluator writes them.
+pub struct RpnEngine {
+    /// Bottom first, X being the last.
+    stack: Vec<String>,
+    /// Number being typed, which stands for X until it is pushed.
+    entry: Option<String>,
+    /// Error of the last key, shown instead of the upper levels.
+    error: Option<RpnError>,
+    context: Context,
+    number_mode: NumberMode,
+}
+
+impl Default for RpnEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RpnEngine {
+    pub fn new() -> RpnEngine {
+        RpnEngine {
+            stack: vec![],
+            entry: None,
+            error: None,
+            context: Context::default(),
+            number_mode: NumberMode::default(),
+        }
+    }
+
+    /// An empty stack, evaluated in the modes `engine` is in.
+    pub fn with_settings_of(engine: &CalculatorEngine) -> RpnEngine {
+        let mut rpn_engine = RpnEngine::new();
+
+        rpn_engine.number_mode = engine.number_mode();
+        rpn_engine.context.angle_mode = engine.angle_mode();
+        rpn_engine.context.word = engine.word();
+        rpn_engine
+    }
+
+    /// X, the number being typed or the top of the stack.
+    pub fn display(&self) -> String {
+        match (&self.entry, self.stack.last()) {
+            (Some(entry), _) => format!("{entry} "),
+            (None, Some(x)) => format!("{x} "),
+            (None, None) => String::from("0 "),
+        }
+    }
+
+    /// The levels above X, `T`, `Z` then `Y`, or the error of the last key.
+    pub fn memory_line(&self) -> String {
+        if let Some(err) = &self.error {
+            return format!("{err}  ");
+        }
+
+        let mut levels: Vec<&str> = self.stack.iter().map(String::as_str).collect();
+
+        if self.entry.is_none() {
+            levels.pop();
+        }
+        let shown = levels.len().min(LEVEL_NAMES.len());
+
+        LEVEL_NAMES[LEVEL_NAMES.len() - shown..]
+            .iter()
+            .zip(&levels[levels.len() - shown..])
+            .map(|(name, value)| format!("{name}: {value}   "))
+            .collect()
+    }
+
+    pub fn caret(&self) -> usize {
+        self.display().trim_end().len()
+    }
+
+    pub fn copy_text(&self) -> String {
+        self.display().trim_end().to_string()
+    }
+
+    /// Every level, bottom first, without the number being typed.
+    pub fn stack(&self) -> &[String] {
+        &self.stack
+    }
+
+    pub fn number_mode(&self) -> NumberMode {
+        self.number_mode
+    }
+
+    pub fn angle_mode(&self) -> AngleMode {
+        self.context.angle_mode
+    }
+
+    pub fn word(&self) -> Word {
+        self.context.word
+    }
+
+    pub fn handle_message(&mut self, msg: MessageEmit) {
+        self.error = None;
+
+        let handled = match msg {
+            MessageEmit::Number(num) => {
+                self.handle_message_number(num);
+                Ok(())
+            },
+            MessageEmit::Dot => {
+                self.handle_message_dot();
+                Ok(())
+            },
+            MessageEmit::RadixPrefix(prefix) => {
+                if self.number_mode.reads_radix_prefixes() && self.entry.as_deref().is_none_or(|entry| entry == "0") {
+                    self.entry = Some(prefix.to_string());
+                }
+                Ok(())
+            },
+            MessageEmit::Negate if self.entry.is_some() => {
+                self.handle_message_negate_entry();
+                Ok(())
+            },
+            MessageEmit::Negate => self.apply_unary(|x| format!("-({x})")),
+            MessageEmit::Equal => self.handle_message_enter(),
+            MessageEmit::Operator(op) => self.apply_binary(|y, x| format!("({y}){op}({x})")),
+            MessageEmit::Percent => self.handle_message_percent(),
+            MessageEmit::Root => self.apply_unary(|x| format!("√({x})")),
+            MessageEmit::Not => self.apply_unary(|x| format!("~({x})")),
+            MessageEmit::Function(name) => self.apply_unary(|x| format!("{name}({x})")),
+            MessageEmit::Constant(name) => self.push_value(name),
+            MessageEmit::Paste(text) => {
+                let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+
+                self.push_value(&text)
+            },
+            MessageEmit::Swap => self.handle_message_swap(),
+            MessageEmit::RollDown => self.handle_message_roll_down(),
+            MessageEmit::Drop => {
+                if self.entry.take().is_none() {
+                    self.stack.pop();
+                }
+                Ok(())
+            },
+            MessageEmit::Delete => {
+                self.handle_message_delete();
+                Ok(())
+            },
+            MessageEmit::Clear => {
+                self.stack.clear();
+                self.entry = None;
+                Ok(())
+            },
+            MessageEmit::CycleAngleMode => {
+                self.context.angle_mode = self.context.angle_mode.next();
+                Ok(())
+            },
+            MessageEmit::SetNumberMode(mode) => {
+                self.number_mode = mode;
+                Ok(())
+            },
+            MessageEmit::CycleWordSize => {
+                self.context.word = self.context.word.next_size();
+                Ok(())
+            },
+            MessageEmit::ToggleSigned => {
+                self.context.word.signed = !self.context.word.signed;
+                Ok(())
+            },
+            // Nothing to do on a stack
+            _ => Ok(()),
+        };
+
+        if let Err(err) = handled {
+            self.error = Some(err);
+        }
+    }
+
+    fn evaluate(&self, expression: &str) -> Result<String, RpnError> {
+        Ok(evaluate_in_mode(expression, self.number_mode, &self.context)?)
+    }
+
+    /// Radix of the number being typed, 10 unless it has a prefix.
+    fn entry_radix(&self) -> u32 {
+        let entry = self.entry.as_deref().unwrap_or_default().trim_start_matches('-');
+
+        RADIX_PREFIXES
+            .iter()
+            .find(|(prefix, _)| entry.starts_with(prefix))
+            .map_or(10, |(_, radix)| *radix)
+    }
+
+    /// Hexadecimal digits start a `0x` number in programmer mode.
+    fn handle_message_number(&mut self, num: u32) {
+        let radix = self.entry_radix();
+        let Some(digit) = char::from_digit(num, 16).map(|c| c.to_ascii_uppercase()) else {
+            return;
+        };
+
+        match self.entry.as_mut() {
+            Some(entry) if num < radix => {
+                if entry == "0" || entry == "-0" {
+                    entry.pop();
+                }
+                entry.push(digit);
+            },
+            Some(_) => (),
+            None if num < 10 => self.entry = Some(digit.to_string()),
+            None if self.number_mode.reads_radix_prefixes() => self.entry = Some(format!("0x{digit}")),
+            None => (),
+        }
+    }
+
+    fn handle_message_dot(&mut self) {
+        if self.entry_radix() != 10 {
+            return;
+        }
+
+        match self.entry.as_mut() {
+            Some(entry) if !entry.contains('.') => entry.push('.'),
+            Some(_) => (),
+            None => self.entry = Some(String::from("0.")),
+        }
+    }
+
+    fn handle_message_negate_entry(&mut self) {
+        if let Some(entry) = self.entry.as_mut() {
+            match entry.strip_prefix('-') {
+                Some(positive) => *entry = positive.to_string(),
+                None => entry.insert(0, '-'),
+            }
+        }
+    }
+
+    /// Backspace while typing, drop otherwise.
+    fn handle_message_delete(&mut self) {
+        match self.entry.as_mut() {
+            Some(entry) => {
+                entry.pop();
+                if entry.is_empty() || entry == "-" {
+                    self.entry = None;
+                }
+            },
+            None => {
+                self.stack.pop();
+            },
+        }
+    }
+
+    /// ENTER pushes the number being typed, or copies X when there is none.
+    fn handle_message_enter(&mut self) -> Result<(), RpnError> {
+        if self.entry.is_some() {
+            return self.push_entry();
+        }
+
+        let x = self.stack.last().ok_or(RpnError::TooFewArguments)?.clone();
+        self.stack.push(x);
+        Ok(())
+    }
+
+    /// X becomes X percent of Y, which stays, as on HP calculators.
+    fn handle_message_percent(&mut self) -> Result<(), RpnError> {
+        self.push_entry()?;
+
+        let [y, x] = self.top_levels::<2>()?;
+        let result = self.evaluate(&format!("({y})x({x})%"))?;
+
+        self.stack.pop();
+        self.stack.push(result);
+        Ok(())
+    }
+
+    fn handle_message_swap(&mut self) -> Result<(), RpnError> {
+        self.push_entry()?;
+        self.top_levels::<2>()?;
+
+        let len = self.stack.len();
+        self.stack.swap(len - 1, len - 2);
+        Ok(())
+    }
+
+    /// X goes to the bottom of the stack, every other level moving up.
+    fn handle_message_roll_down(&mut self) -> Result<(), RpnError> {
+        self.push_entry()?;
+
+        if !self.stack.is_empty() {
+            self.stack.rotate_right(1);
+        }
+        Ok(())
+    }
+
+    /// Evaluates the number being typed onto the stack, so that `0x1F` or
+    /// `1.` are kept as `31` and `1`.
+    fn push_entry(&mut self) -> Result<(), RpnError> {
+        let Some(entry) = self.entry.as_deref() else {
+            return Ok(());
+        };
+        let value = self.evaluate(entry)?;
+
+        self.entry = None;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn push_value(&mut self, expression: &str) -> Result<(), RpnError> {
+        self.push_entry()?;
+
+        let value = self.evaluate(expression)?;
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Clones of the `N` levels ending with X, the stack being left as is.
+    fn top_levels<const N: usize>(&self) -> Result<[String; N], RpnError> {
+        let start = self.stack.len().checked_sub(N).ok_or(RpnError::TooFewArguments)?;
+
+        Ok(std::array::from_fn(|index| self.stack[start + index].clone()))
+    }
+
+    /// Replaces X with `expression(x)`. On error, the operands stay on the stack.
+    fn apply_unary<F: Fn(&str) -> String>(&mut self, expression: F) -> Result<(), RpnError> {
+        self.push_entry()?;
+
+        let [x] = self.top_levels::<1>()?;
+        let result = self.evaluate(&expression(&x))?;
+
+        self.stack.pop();
+        self.stack.push(result);
+        Ok(())
+    }
+
+    /// Replaces Y and X with `expression(y, x)`.
+    fn apply_binary<F: Fn(&str, &str) -> String>(&mut self, expression: F) -> Result<(), RpnError> {
+        self.push_entry()?;
+
+        let [y, x] = self.top_levels::<2>()?;
+        let result = self.evaluate(&expression(&y, &x))?;
+
+        self.stack.truncate(self.stack.len() - 2);
+        self.stack.push(result);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RpnEngine;
+    use crate::engine::MessageEmit;
+    use crate::number::NumberMode;
+
+    #[test]
+    fn handle_message_tests() {
+        let data = vec![
+            (vec![MessageEmit::Number(3), MessageEmit::Equal, MessageEmit::Number(4), MessageEmit::Operator('+')], "7 ", ""),
+            (vec![MessageEmit::Number(3), MessageEmit::Equal, MessageEmit::Number(4)], "4 ", "Y: 3   "),
+            (vec![MessageEmit::Number(3), MessageEmit::Equal, MessageEmit::Equal], "3 ", "Y: 3   "),
+            (vec![MessageEmit::Number(1), MessageEmit::Equal, MessageEmit::Number(2), MessageEmit::Equal, MessageEmit::Number(3), MessageEmit::Equal, MessageEmit::Number(4), MessageEmit::Equal, MessageEmit::Number(5)], "5 ", "T: 2   Z: 3   Y: 4   "),
+            (vec![MessageEmit::Number(1), MessageEmit::Number(0), MessageEmit::Equal, MessageEmit::Number(4), MessageEmit::Operator('-')], "6 ", ""),
+            (vec![MessageEmit::Number(1), MessageEmit::Number(0), MessageEmit::Equal, MessageEmit::Number(4), MessageEmit::Swap, MessageEmit::Operator('-')], "-6 ", ""),
+            (vec![MessageEmit::Number(1), MessageEmit::Equal, MessageEmit::Number(2), MessageEmit::Equal, MessageEmit::Number(3), MessageEmit::RollDown], "2 ", "Z: 3   Y: 1   "),
+            (vec![MessageEmit::Number(1), MessageEmit::Equal, MessageEmit::Number(2), MessageEmit::Drop], "1 ", ""),
+            (vec![MessageEmit::Number(1), MessageEmit::Equal, MessageEmit::Number(2), MessageEmit::Equal, MessageEmit::Drop], "1 ", ""),
+            (vec![MessageEmit::Number(1), MessageEmit::Number(2), MessageEmit::Delete], "1 ", ""),
+            (vec![MessageEmit::Number(1), MessageEmit::Equal, MessageEmit::Number(2), MessageEmit::Delete, MessageEmit::Delete], "0 ", ""),
+            (vec![MessageEmit::Number(5), MessageEmit::Operator('+')], "5 ", "Too few arguments  "),
+            (vec![MessageEmit::Number(1), MessageEmit::Equal, MessageEmit::Number(0), MessageEmit::Operator('/')], "0 ", "Division by zero  "),
+            (vec![MessageEmit::Number(9), MessageEmit::Root, MessageEmit::Negate], "-3 ", ""),
+            (vec![MessageEmit::Number(9), MessageEmit::Negate, MessageEmit::Dot, MessageEmit::Number(5)], "-9.5 ", ""),
+            (vec![MessageEmit::Number(2), MessageEmit::Number(0), MessageEmit::Number(0), MessageEmit::Equal, MessageEmit::Number(5), MessageEmit::Percent], "10 ", "Y: 200   "),
+            (vec![MessageEmit::Number(9), MessageEmit::Number(0), MessageEmit::Function("sin"), MessageEmit::Constant("π"), MessageEmit::Operator('x')], "3.1415926535897932384626433832795 ", ""),
+            (vec![MessageEmit::Paste(String::from("2 x 3")), MessageEmit::Number(1), MessageEmit::Operator('+')], "7 ", ""),
+            (vec![MessageEmit::Number(1), MessageEmit::Equal, MessageEmit::Number(2), MessageEmit::Clear], "0 ", ""),
+            (vec![MessageEmit::Parentheses('('), MessageEmit::Number(2)], "2 ", ""),
+        ];
+
+        for (messages, expected_display, expected_line) in data {
+            let mut engine = RpnEngine::new();
+
+            for msg in messages.clone() {
+                engine.handle_message(msg);
+            }
+            assert_eq!(engine.display(), expected_display, "Failed test for {messages:?}");
+            assert_eq!(engine.memory_line(), expected_line, "Failed test for {messages:?}");
+        }
+    }
+
+    #[test]
+    fn number_mode_tests() {
+        let mut engine = RpnEngine::new();
+
+        engine.handle_message(MessageEmit::SetNumberMode(NumberMode::Programmer));
+        for msg in [MessageEmit::Number(15), MessageEmit::Number(15), MessageEmit::Equal, MessageEmit::RadixPrefix("0b"), MessageEmit::Number(1), MessageEmit::Number(2), MessageEmit::Number(0)] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "0b10 ");
+
+        engine.handle_message(MessageEmit::Operator('&'));
+        assert_eq!(engine.display(), "2 ");
+
+        engine.handle_message(MessageEmit::SetNumberMode(NumberMode::Rational));
+        for msg in [MessageEmit::Number(3), MessageEmit::Operator('/')] {
+            engine.handle_message(msg);
+        }
+        assert_eq!(engine.display(), "2/3 ");
+        assert_eq!(engine.stack(), ["2/3"]);
+    }
+}
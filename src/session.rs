@@ -0,0 +1,300 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::ast::AngleMode;
+use crate::history::History;
+use crate::number::{NumberMode, Word};
+
+/// First line of a session file. Bump the number when the format changes.
+const HEADER: &str = "desktop-calculator-session";
+pub const FORMAT_VERSION: u32 = 5;
+
+/// Older versions still read, their missing records keep their default.
+const READABLE_VERSIONS: [u32; 5] = [1, 2, 3, 4, 5];
+
+/// What is kept from one run of the calculator to the next.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Session {
+    pub display: String,
+    pub memory: String,
+    pub history: History,
+    /// Since version 2.
+    pub register: String,
+    /// Since version 2, as `(name, value)` pairs.
+    pub stores: Vec<(char, String)>,
+    /// Since version 2.
+    pub angle_mode: AngleMode,
+    /// Since version 2.
+    pub number_mode: NumberMode,
+    /// Since version 3.
+    pub word: Word,
+    /// Since version 4, as `(name, value)` pairs, the value being written
+    /// as an expression.
+    pub variables: Vec<(String, String)>,
+    /// Since version 5, each written as its definition, e.g. `f(x,y)=x^2+y`.
+    pub functions: Vec<String>,
+}
+
+impl Default for Session {
+    fn default() -> Session {
+        Session {
+            display: String::from("0 "),
+            memory: String::new(),
+            history: History::new(),
+            register: String::from("0"),
+            stores: vec![],
+            angle_mode: AngleMode::default(),
+            number_mode: NumberMode::default(),
+            word: Word::default(),
+            variables: vec![],
+            functions: vec![],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Io(io::Error),
+    UnsupportedVersion(String),
+    Corrupt { line: usize },
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Io(err) => write!(f, "{err}"),
+            SessionError::UnsupportedVersion(version) => write!(f, "Unsupported session version '{version}'"),
+            SessionError::Corrupt { line } => write!(f, "Corrupt session file at line {line}"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<io::Error> for SessionError {
+    fn from(err: io::Error) -> SessionError {
+        SessionError::Io(err)
+    }
+}
+
+/// `$XDG_DATA_HOME/desktop-calculator/session`, falling back on
+/// `~/.local/share` when the variable is unset.
+pub fn default_path() -> Option<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))?;
+
+    Some(data_home.join("desktop-calculator").join("session"))
+}
+
+impl Session {
+    /// Reads the session at `path`, along with the records it skipped.
+    pub fn load(path: &Path) -> Result<(Session, Vec<SessionError>), SessionError> {
+        Session::from_text(&fs::read_to_string(path)?)
+    }
+
+    /// Writes a temporary file next to `path` first, then renames it, so that
+    /// a crash while saving leaves the previous session whole.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temporary_path = path.with_extension("tmp");
+        let mut file = fs::File::create(&temporary_path)?;
+
+        file.write_all(self.to_text().as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary_path, path)
+    }
+
+    /// One tab-separated record per line, after a versioned header:
+    /// the settings, `display`, `memory`, the registers, the variables and
+    /// functions, then an `entry` per calculation, oldest first.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{HEADER} {FORMAT_VERSION}\n");
+
+        text.push_str(&format!("angle\t{}\n", self.angle_mode));
+        text.push_str(&format!("numbers\t{}\n", self.number_mode.name()));
+        text.push_str(&format!("word\t{}\n", self.word.name()));
+        text.push_str(&format!("display\t{}\n", self.display));
+        text.push_str(&format!("memory\t{}\n", self.memory));
+        text.push_str(&format!("register\t{}\n", self.register));
+        for (name, value) in &self.stores {
+            text.push_str(&format!("store\t{name}\t{value}\n"));
+        }
+        for (name, value) in &self.variables {
+            text.push_str(&format!("variable\t{name}\t{value}\n"));
+        }
+        for definition in &self.functions {
+            text.push_str(&format!("function\t{definition}\n"));
+        }
+        for entry in self.history.entries() {
+            text.push_str(&format!("entry\t{}\t{}\n", entry.expression, entry.result));
+        }
+
+        text
+    }
+
+    /// A record that is unknown or cannot be read is skipped, the others
+    /// being kept. The skipped records come along with the session.
+    pub fn from_text(text: &str) -> Result<(Session, Vec<SessionError>), SessionError> {
+        let mut lines = text.lines();
+        let version = lines
+            .next()
+            .and_then(|header| header.strip_prefix(HEADER))
+            .ok_or(SessionError::Corrupt { line: 1 })?
+            .trim();
+
+        if !READABLE_VERSIONS.iter().any(|readable| readable.to_string() == version) {
+            return Err(SessionError::UnsupportedVersion(version.to_string()));
+        }
+
+        let mut session = Session::default();
+        let mut skipped: Vec<SessionError> = vec![];
+
+        for (index, line) in lines.enumerate() {
+            if session.read_record(line).is_none() {
+                skipped.push(SessionError::Corrupt { line: index + 2 });
+            }
+        }
+
+        Ok((session, skipped))
+    }
+
+    /// Reads one line of `to_text`, or nothing if it is not a valid record.
+    fn read_record(&mut self, line: &str) -> Option<()> {
+        let fields: Vec<&str> = line.split('\t').collect();
+
+        match fields[..] {
+            ["angle", name] => self.angle_mode = AngleMode::from_name(name)?,
+            ["numbers", name] => self.number_mode = NumberMode::from_name(name)?,
+            ["word", name] => self.word = Word::from_name(name)?,
+            ["display", display] => self.display = display.to_string(),
+            ["memory", memory] => self.memory = memory.to_string(),
+            ["register", register] => self.register = register.to_string(),
+            ["store", name, value] => {
+                let mut chars = name.chars();
+
+                match (chars.next(), chars.next()) {
+                    (Some(name), None) => self.stores.push((name, value.to_string())),
+                    _ => return None,
+                }
+            },
+            ["variable", name, value] if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphabetic()) => {
+                self.variables.push((name.to_string(), value.to_string()));
+            },
+            ["function", definition] => self.functions.push(definition.to_string()),
+            ["entry", expression, result] => self.history.push(expression, result),
+            _ => return None,
+        }
+
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Session, SessionError};
+    use crate::ast::AngleMode;
+    use crate::number::{NumberMode, Word};
+    use std::fs;
+
+    #[test]
+    fn session_round_trip_tests() {
+        let mut session = Session {
+            display: String::from("1 200 + "),
+            memory: String::from("2 x 3 =  "),
+            register: String::from("-1.5"),
+            stores: vec![('A', String::from("7⌟2")), ('F', String::from("12"))],
+            angle_mode: AngleMode::Gradians,
+            number_mode: NumberMode::Rational,
+            word: Word { bits: 16, signed: false },
+            variables: vec![(String::from("rate"), String::from("0.07")), (String::from("third"), String::from("1/3"))],
+            functions: vec![String::from("f(x,y)=x^2+y")],
+            ..Session::default()
+        };
+        session.history.push("2 x 3", "6");
+        session.history.push("7 / 2", "3.5");
+
+        let text = session.to_text();
+
+        assert!(text.starts_with("desktop-calculator-session 5\n"));
+        let (read, skipped) = Session::from_text(&text).unwrap();
+        assert_eq!(read, session);
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn session_version_1_tests() {
+        let (session, _) = Session::from_text("desktop-calculator-session 1\ndisplay\t12 \nmemory\t\nentry\t6 x 2\t12\n").unwrap();
+
+        assert_eq!(session.display, "12 ");
+        assert_eq!(session.register, "0");
+        assert_eq!(session.angle_mode, AngleMode::Degrees);
+        assert_eq!(session.number_mode, NumberMode::Decimal);
+        assert_eq!(session.history.len(), 1);
+    }
+
+    #[test]
+    fn session_save_and_load_tests() {
+        let path = std::env::temp_dir()
+            .join(format!("desktop-calculator-test-{}", std::process::id()))
+            .join("session");
+        let session = Session {
+            display: String::from("42 "),
+            ..Session::default()
+        };
+
+        session.save(&path).unwrap();
+        assert_eq!(Session::load(&path).unwrap().0, session);
+        assert!(!path.with_extension("tmp").exists());
+
+        // Saving again replaces the previous session
+        let session = Session { display: String::from("7 "), ..session };
+        session.save(&path).unwrap();
+        assert_eq!(Session::load(&path).unwrap().0, session);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert!(matches!(Session::load(&path), Err(SessionError::Io(_))));
+    }
+
+    #[test]
+    fn session_errors_tests() {
+        let data: Vec<(&str, &str)> = vec![
+            ("", "Corrupt session file at line 1"),
+            ("hello", "Corrupt session file at line 1"),
+            ("desktop-calculator-session 6\n", "Unsupported session version '6'"),
+        ];
+
+        for (text, expected_error) in data {
+            let err: SessionError = Session::from_text(text).unwrap_err();
+            assert_eq!(err.to_string(), expected_error, "Failed test for {text:?}");
+        }
+    }
+
+    #[test]
+    fn session_skipped_records_tests() {
+        let data: Vec<(&str, &str)> = vec![
+            ("desktop-calculator-session 4\nvariable\tr2\t1\n", "Corrupt session file at line 2"),
+            ("desktop-calculator-session 3\nword\tu12\n", "Corrupt session file at line 2"),
+            ("desktop-calculator-session 2\nangle\tDEGREES\n", "Corrupt session file at line 2"),
+            ("desktop-calculator-session 2\nstore\tAB\t1\n", "Corrupt session file at line 2"),
+            ("desktop-calculator-session 1\ndisplay\t0 \nentry\t1 + 1\n", "Corrupt session file at line 3"),
+            ("desktop-calculator-session 1\nresult\t0\n", "Corrupt session file at line 2"),
+        ];
+
+        for (text, expected_error) in data {
+            let (_, skipped) = Session::from_text(text).unwrap();
+            let skipped: Vec<String> = skipped.iter().map(|err| err.to_string()).collect();
+            assert_eq!(skipped, [expected_error], "Failed test for {text:?}");
+        }
+
+        let (session, skipped) = Session::from_text("desktop-calculator-session 5\ndisplay\t12 \nlayout\twide\nentry\t6 x 2\t12\n").unwrap();
+        assert_eq!(session.display, "12 ");
+        assert_eq!(session.history.len(), 1);
+        assert_eq!(skipped.len(), 1);
+    }
+}